@@ -16,6 +16,10 @@
 #![allow(dead_code, clippy::module_name_repetitions)]
 
 use crate::msg_listener::Action;
+use crate::x11::layout::LayoutKind;
+use crate::x11::policy::{Policy, PolicyOverride, WindowType};
+use crate::x11::rules::{Pattern, Rule};
+use crate::x11::tree::Direction;
 use crate::Result;
 use breadx::auto::xproto::KeyButMask;
 use serde::{
@@ -24,14 +28,27 @@ use serde::{
 };
 use std::collections::HashMap;
 use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct XcrabConfig {
     border_color: Option<u32>,
     focused_color: Option<u32>,
+    hung_color: Option<u32>,
     border_size: Option<u16>,
     gap_size: Option<u16>,
     outer_gap_size: Option<u16>,
+    tab_height: Option<u16>,
+    min_client_size: Option<u16>,
+    tab_color: Option<u32>,
+    tab_text_color: Option<u32>,
+    layout: Option<LayoutKind>,
+    workspace_layouts: Option<HashMap<String, LayoutKind>>,
+    master_ratio: Option<f64>,
+    workspaces: Option<Vec<String>>,
+    window_types: Option<HashMap<WindowType, PolicyOverride>>,
+    close_timeout: Option<u64>,
+    rules: Option<Vec<Rule>>,
     pub msg: Option<XcrabMsgConfig>,
     #[allow(clippy::zero_sized_map_values)] // TODO: Action will be expanded in the future
     #[serde(default)]
@@ -45,17 +62,36 @@ pub struct XcrabMsgConfig {
 
 const DEFAULT_BORDER_COLOR: u32 = 0xff_00_00; // red
 const DEFAULT_FOCUSED_COLOR: u32 = 0x00_00_ff; // blue
+const DEFAULT_HUNG_COLOR: u32 = 0x80_80_80; // grey
 const DEFAULT_BORDER_SIZE: u16 = 5;
 const DEFAULT_GAP_SIZE: u16 = 20;
+const DEFAULT_TAB_HEIGHT: u16 = 20;
+const DEFAULT_MIN_CLIENT_SIZE: u16 = 50;
+const DEFAULT_TAB_COLOR: u32 = 0x33_33_33; // dark grey
+const DEFAULT_TAB_TEXT_COLOR: u32 = 0xff_ff_ff; // white
+const DEFAULT_MASTER_RATIO: f64 = 0.55;
+const DEFAULT_CLOSE_TIMEOUT: u64 = 5000;
 
 impl Default for XcrabConfig {
     fn default() -> Self {
         Self {
             border_color: Some(DEFAULT_BORDER_COLOR),
             focused_color: Some(DEFAULT_FOCUSED_COLOR),
+            hung_color: Some(DEFAULT_HUNG_COLOR),
             border_size: Some(DEFAULT_BORDER_SIZE),
             gap_size: Some(DEFAULT_GAP_SIZE),
             outer_gap_size: None,
+            tab_height: Some(DEFAULT_TAB_HEIGHT),
+            min_client_size: Some(DEFAULT_MIN_CLIENT_SIZE),
+            tab_color: Some(DEFAULT_TAB_COLOR),
+            tab_text_color: Some(DEFAULT_TAB_TEXT_COLOR),
+            layout: Some(LayoutKind::default()),
+            workspace_layouts: None,
+            master_ratio: Some(DEFAULT_MASTER_RATIO),
+            workspaces: Some(default_workspaces()),
+            window_types: None,
+            close_timeout: Some(DEFAULT_CLOSE_TIMEOUT),
+            rules: None,
             msg: Some(XcrabMsgConfig::default()),
             binds: HashMap::new(),
         }
@@ -66,7 +102,7 @@ impl Default for XcrabMsgConfig {
     fn default() -> Self {
         let home_dir = get_home().expect("Error: $HOME variable not set");
         Self {
-            socket_path: format!("{}/.config/xcrab/msg.sock", home_dir).into(),
+            socket_path: format!("{home_dir}/.config/xcrab/msg.sock").into(),
         }
     }
 }
@@ -80,6 +116,11 @@ impl XcrabConfig {
         self.focused_color.unwrap_or(DEFAULT_FOCUSED_COLOR)
     }
 
+    /// The border of a client that didn't answer when we asked it to close.
+    pub fn hung_color(&self) -> u32 {
+        self.hung_color.unwrap_or(DEFAULT_HUNG_COLOR)
+    }
+
     pub fn border_size(&self) -> u16 {
         self.border_size.unwrap_or(DEFAULT_BORDER_SIZE)
     }
@@ -91,12 +132,82 @@ impl XcrabConfig {
     pub fn outer_gap_size(&self) -> u16 {
         self.outer_gap_size.unwrap_or_else(|| self.gap_size())
     }
+
+    pub fn tab_height(&self) -> u16 {
+        self.tab_height.unwrap_or(DEFAULT_TAB_HEIGHT)
+    }
+
+    /// The smallest a tiled client's frame is made, in both directions, before the pane it is
+    /// in gives up on showing its children side by side and tabs them instead. It's always big
+    /// enough for the borders and at least a pixel of the client.
+    pub fn min_client_size(&self) -> u16 {
+        self.min_client_size
+            .unwrap_or(DEFAULT_MIN_CLIENT_SIZE)
+            .max(self.border_size().saturating_mul(2).saturating_add(1))
+    }
+
+    pub fn tab_color(&self) -> u32 {
+        self.tab_color.unwrap_or(DEFAULT_TAB_COLOR)
+    }
+
+    pub fn tab_text_color(&self) -> u32 {
+        self.tab_text_color.unwrap_or(DEFAULT_TAB_TEXT_COLOR)
+    }
+
+    /// The layout `workspace` starts out with: its entry in `[workspace_layouts]`, or `layout`
+    /// if it doesn't have one.
+    pub fn layout(&self, workspace: &str) -> LayoutKind {
+        self.workspace_layouts
+            .as_ref()
+            .and_then(|layouts| layouts.get(workspace).copied())
+            .or(self.layout)
+            .unwrap_or_default()
+    }
+
+    pub fn master_ratio(&self) -> f64 {
+        self.master_ratio.unwrap_or(DEFAULT_MASTER_RATIO)
+    }
+
+    /// The workspaces that exist from the start. More are created as they are switched to.
+    pub fn workspaces(&self) -> Vec<String> {
+        self.workspaces
+            .clone()
+            .filter(|workspaces| !workspaces.is_empty())
+            .unwrap_or_else(default_workspaces)
+    }
+
+    /// How long, in milliseconds in the config, a client gets to close after being asked to
+    /// before it's killed. Clients that answer `_NET_WM_PING` are marked as hung halfway through
+    /// if they don't, and can take as long as they like if they do.
+    pub fn close_timeout(&self) -> Duration {
+        Duration::from_millis(self.close_timeout.unwrap_or(DEFAULT_CLOSE_TIMEOUT))
+    }
+
+    /// What to do with windows of type `ty`: the built in policy, with whatever the
+    /// `[window_types.<type>]` table changes.
+    pub fn window_policy(&self, ty: WindowType) -> Policy {
+        let policy = Policy::default_for(ty);
+
+        match self.window_types.as_ref().and_then(|types| types.get(&ty)) {
+            Some(overrides) => policy.with(overrides),
+            None => policy,
+        }
+    }
+
+    /// The `[[rules]]` tables, in the order they're checked.
+    pub fn rules(&self) -> &[Rule] {
+        self.rules.as_deref().unwrap_or_default()
+    }
+}
+
+fn default_workspaces() -> Vec<String> {
+    (1..=9).map(|i| i.to_string()).collect()
 }
 
 pub fn load_file() -> Result<XcrabConfig> {
     let home_dir = get_home()?;
 
-    let contents = std::fs::read_to_string(format!("{}/.config/xcrab/config.toml", home_dir))?;
+    let contents = std::fs::read_to_string(format!("{home_dir}/.config/xcrab/config.toml"))?;
 
     let config: XcrabConfig = toml::from_str(&contents)?;
 
@@ -108,7 +219,7 @@ fn get_home() -> Result<String> {
 }
 
 struct ActionVisitor;
-impl<'de> Visitor<'de> for ActionVisitor {
+impl Visitor<'_> for ActionVisitor {
     type Value = Action;
 
     fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -126,6 +237,82 @@ impl<'de> Deserialize<'de> for Action {
     }
 }
 
+struct LayoutKindVisitor;
+impl Visitor<'_> for LayoutKindVisitor {
+    type Value = LayoutKind;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("the name of a layout")
+    }
+
+    fn visit_str<E: serde::de::Error>(self, value: &str) -> std::result::Result<Self::Value, E> {
+        value.parse().map_err(|s| E::custom(s))
+    }
+}
+
+impl<'de> Deserialize<'de> for LayoutKind {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        deserializer.deserialize_str(LayoutKindVisitor)
+    }
+}
+
+struct WindowTypeVisitor;
+impl Visitor<'_> for WindowTypeVisitor {
+    type Value = WindowType;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("the name of a window type")
+    }
+
+    fn visit_str<E: serde::de::Error>(self, value: &str) -> std::result::Result<Self::Value, E> {
+        value.parse().map_err(|s| E::custom(s))
+    }
+}
+
+impl<'de> Deserialize<'de> for WindowType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        deserializer.deserialize_str(WindowTypeVisitor)
+    }
+}
+
+struct DirectionVisitor;
+impl Visitor<'_> for DirectionVisitor {
+    type Value = Direction;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("up, down, left or right")
+    }
+
+    fn visit_str<E: serde::de::Error>(self, value: &str) -> std::result::Result<Self::Value, E> {
+        value.parse().map_err(|s| E::custom(s))
+    }
+}
+
+impl<'de> Deserialize<'de> for Direction {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        deserializer.deserialize_str(DirectionVisitor)
+    }
+}
+
+struct PatternVisitor;
+impl Visitor<'_> for PatternVisitor {
+    type Value = Pattern;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a regex")
+    }
+
+    fn visit_str<E: serde::de::Error>(self, value: &str) -> std::result::Result<Self::Value, E> {
+        regex::Regex::new(value).map(Pattern).map_err(E::custom)
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        deserializer.deserialize_str(PatternVisitor)
+    }
+}
+
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
 pub struct Keybind {
     pub key: char,
@@ -133,7 +320,7 @@ pub struct Keybind {
 }
 
 struct KeybindVisitor;
-impl<'de> Visitor<'de> for KeybindVisitor {
+impl Visitor<'_> for KeybindVisitor {
     type Value = Keybind;
 
     fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -160,7 +347,7 @@ impl<'de> Visitor<'de> for KeybindVisitor {
                     'S' => mask.set_shift(true),
                     'A' => mask.set_mod1(true), // alt key
                     'W' => mask.set_mod4(true), // super key, 'w' for windows because S is taken
-                    _ => return Err(E::custom(format!("no such modifier: {}", c))),
+                    _ => return Err(E::custom(format!("no such modifier: {c}"))),
                 };
             }
         }
@@ -16,9 +16,11 @@
 #![warn(clippy::pedantic)]
 
 use std::fmt::{Debug, Display};
+use std::sync::LazyLock;
+use std::time::Instant;
 
 use breadx::{
-    auto::xproto::{GrabKeyRequest, GrabMode, KeyButMask, Keycode, Keysym, ModMask},
+    auto::xproto::{GrabKeyRequest, GrabMode, KeyButMask, Keycode, ModMask},
     keyboard::KeyboardState,
     prelude::{AsyncDisplay, AsyncDisplayXprotoExt, MapState},
     traits::DisplayBase,
@@ -26,17 +28,17 @@ use breadx::{
     EventMask, Window,
 };
 
-use lazy_static::lazy_static;
-
 use tokio::sync::mpsc::unbounded_channel;
 
 mod config;
 mod msg_listener;
 mod x11;
 
-use x11::client::{may_not_exist, XcrabWindowManager};
-
-use std::collections::HashMap;
+use x11::{
+    atoms::Atoms,
+    client::{may_not_exist, XcrabWindowManager},
+    policy::Manage,
+};
 
 #[non_exhaustive]
 pub enum XcrabError {
@@ -78,13 +80,13 @@ impl From<String> for XcrabError {
     }
 }
 
-lazy_static! {
-    pub static ref CONFIG: config::XcrabConfig = config::load_file().unwrap_or_else(|e| {
+pub static CONFIG: LazyLock<config::XcrabConfig> = LazyLock::new(|| {
+    config::load_file().unwrap_or_else(|e| {
         println!("[CONFIG] Error parsing config: {e}");
         println!("[CONFIG] Falling back to default config");
         config::XcrabConfig::default()
-    });
-}
+    })
+});
 
 impl Display for XcrabError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -95,7 +97,7 @@ impl Display for XcrabError {
             Self::Var(ve) => Display::fmt(ve, f)?,
             Self::ClientDoesntExist => Display::fmt("client didn't exist", f)?,
             Self::Custom(fe) => Display::fmt(fe, f)?,
-        };
+        }
 
         Ok(())
     }
@@ -109,6 +111,9 @@ impl Debug for XcrabError {
 
 type Result<T> = std::result::Result<T, XcrabError>;
 
+/// Shift, control, mod1 (alt), mod3, mod4 (super) and mod5.
+const BIND_MODIFIERS: u16 = 0b1110_1101;
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // connect to the x server
@@ -116,38 +121,46 @@ async fn main() -> Result<()> {
 
     let root = conn.default_root();
 
-    // listen for substructure redirects to intercept events like window creation
+    // listen for substructure redirects to intercept events like window creation, and for
+    // the root window itself changing size
     root.set_event_mask_async(
         &mut conn,
-        EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY | EventMask::KEY_PRESS,
+        EventMask::SUBSTRUCTURE_REDIRECT
+            | EventMask::SUBSTRUCTURE_NOTIFY
+            | EventMask::STRUCTURE_NOTIFY
+            | EventMask::KEY_PRESS,
     )
     .await?;
 
-    let mut manager = XcrabWindowManager::new();
+    let atoms = Atoms::intern(&mut conn).await?;
+
+    let mut manager = XcrabWindowManager::new(atoms);
+    manager.init_outputs(&mut conn).await?;
+    manager.init_ewmh(&mut conn).await?;
 
     conn.grab_server_async().await?;
 
     let top_level_windows = root.query_tree_immediate_async(&mut conn).await?.children;
 
-    for &win in top_level_windows.iter() {
+    for &win in &top_level_windows {
         let attrs = win.window_attributes_immediate_async(&mut conn).await?;
 
-        if !attrs.override_redirect && attrs.map_state == MapState::Viewable {
-            manager.add_client(&mut conn, win).await?;
+        if attrs.override_redirect || attrs.map_state != MapState::Viewable {
+            continue;
         }
+
+        manage(&mut manager, &mut conn, win).await?;
     }
 
     conn.ungrab_server_async().await?;
 
-    let mut mask = ModMask::new(false, false, true, false, false, false, false, false, false);
     let mut keyboard_state = KeyboardState::new_async(&mut conn).await?;
     let keymap = x11::client::keymap(&mut keyboard_state);
-    let mut request_key = *keymap.get(&120).ok_or_else(|| {
-        XcrabError::Custom("At least one letter could not be found in the keymap".to_string())
-    })?;
 
-    for &binds in CONFIG.binds.keys() {
-        for keysym in 97..122_u32 {
+    for &bind in CONFIG.binds.keys() {
+        let mut request_key = None;
+
+        for keysym in 97..=122_u32 {
             let keycode = keymap.get(&keysym).ok_or_else(|| {
                 XcrabError::Custom(
                     "At least one letter could not be found in the keymap".to_string(),
@@ -164,40 +177,21 @@ async fn main() -> Result<()> {
                 .ok_or_else(|| {
                     XcrabError::Custom("The processed Key could not be cast as a char".to_string())
                 })?;
-            if iter_char == binds.key {
-                request_key = *keycode;
-                mask.inner = binds.mods.inner;
+            if iter_char == bind.key {
+                request_key = Some(*keycode);
+                break;
             }
         }
-    }
 
-    mask.set_Two(true);
-
-    conn.exchange_request_async(GrabKeyRequest {
-        req_type: 33,
-        owner_events: false,
-        length: 4,
-        grab_window: root,
-        modifiers: mask,
-        key: request_key,
-        pointer_mode: GrabMode::Async,
-        keyboard_mode: GrabMode::Async,
-    })
-    .await?;
+        let request_key = request_key.ok_or_else(|| {
+            XcrabError::Custom(format!(
+                "The key {} could not be found in the keymap",
+                bind.key
+            ))
+        })?;
 
-    mask.set_Two(false);
-
-    conn.exchange_request_async(GrabKeyRequest {
-        req_type: 33,
-        owner_events: false,
-        length: 4,
-        grab_window: root,
-        modifiers: mask,
-        key: request_key,
-        pointer_mode: GrabMode::Async,
-        keyboard_mode: GrabMode::Async,
-    })
-    .await?;
+        grab_key(&mut conn, root, request_key, bind.mods).await?;
+    }
 
     let (send, mut recv) = unbounded_channel();
     let (result_send, result_recv) = unbounded_channel();
@@ -215,10 +209,66 @@ async fn main() -> Result<()> {
             biased;
             Some(s) = recv.recv() => msg_listener::on_recv(s, &mut manager, &mut conn, &result_send).await?,
             Ok(ev) = conn.wait_for_event_async() => process_event(ev, &mut manager, &mut conn, root, &mut keyboard_state).await?,
+            () = sleep_until(manager.close_deadline()) => manager.check_closing(&mut conn).await?,
         }
     }
 }
 
+/// Waits until `deadline`, or forever if there isn't one.
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Grabs `key` on `root` with the given modifiers, both with and without numlock (`Mod2`) held.
+async fn grab_key<Dpy: AsyncDisplay + ?Sized>(
+    conn: &mut Dpy,
+    root: Window,
+    key: Keycode,
+    mods: KeyButMask,
+) -> Result<()> {
+    let mut mask = ModMask { inner: mods.inner };
+
+    for numlock in [true, false] {
+        mask.set_Two(numlock);
+
+        conn.exchange_request_async(GrabKeyRequest {
+            req_type: 33,
+            owner_events: false,
+            length: 4,
+            grab_window: root,
+            modifiers: mask,
+            key,
+            pointer_mode: GrabMode::Async,
+            keyboard_mode: GrabMode::Async,
+        })
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Starts managing a window that is (or wants to be) mapped, however its window type says to.
+async fn manage<Dpy: AsyncDisplay + ?Sized>(
+    manager: &mut XcrabWindowManager,
+    conn: &mut Dpy,
+    win: Window,
+) -> Result<()> {
+    let (policy, actions, metadata) = manager.window_policy(conn, win).await?;
+
+    match policy.manage {
+        Manage::Tile | Manage::Float => {
+            manager
+                .add_client(conn, win, policy, &actions, metadata)
+                .await
+        }
+        Manage::Dock => manager.add_dock(conn, win).await,
+        Manage::Ignore => manager.add_unmanaged(conn, win, policy).await,
+    }
+}
+
 #[allow(clippy::too_many_lines)]
 async fn process_event<Dpy: AsyncDisplay + ?Sized>(
     ev: Event,
@@ -227,9 +277,20 @@ async fn process_event<Dpy: AsyncDisplay + ?Sized>(
     root: Window,
     keyboard_state: &mut KeyboardState,
 ) -> Result<()> {
+    // `WM_TAKE_FOCUS` has to be sent with the time of whatever made us change focus
+    match &ev {
+        Event::KeyPress(ev) => manager.set_time(ev.time),
+        Event::ButtonPress(ev) => manager.set_time(ev.time),
+        Event::PropertyNotify(ev) => manager.set_time(ev.time),
+        _ => {}
+    }
+
     match ev {
         Event::MapRequest(ev) => {
-            manager.add_client(conn, ev.window).await?;
+            manage(manager, conn, ev.window).await?;
+        }
+        Event::ConfigureRequest(ev) if manager.is_floating(ev.window) => {
+            manager.configure_floating(conn, &ev).await?;
         }
         Event::ConfigureRequest(ev) => {
             // copy from `ev` to `params`
@@ -245,33 +306,78 @@ async fn process_event<Dpy: AsyncDisplay + ?Sized>(
                 stack_mode: Some(ev.stack_mode),
             };
 
-            // if this is a client, deny changing position or size (we are a tiling wm!)
-            if manager.has_client(ev.window) {
+            // if this is a client, deny changing position or size (we are a tiling wm!), and
+            // its border, which we keep at zero so it fills its frame
+            let client = manager.has_client(ev.window);
+
+            if client {
                 params.x = None;
                 params.y = None;
                 params.width = None;
                 params.height = None;
+                params.border_width = None;
             }
 
             // forward the request
             // by the time we get here someone may have already deleted their window
             may_not_exist(ev.window.configure_async(conn, params).await)?;
-        }
-        Event::UnmapNotify(ev) => {
-            if ev.event != root && manager.has_client(ev.window) {
-                manager.remove_client(conn, ev.window).await?;
+
+            // the icccm says a client whose request we didn't grant has to hear where it is
+            if client {
+                manager.deny_configure(conn, ev.window).await?;
             }
         }
-        Event::ButtonPress(ev) => {
-            if ev.detail == 1 {
+        Event::UnmapNotify(ev) if manager.has_dock(ev.window) => {
+            manager.remove_dock(conn, ev.window).await?;
+        }
+        Event::UnmapNotify(ev) if manager.has_client(ev.window) => {
+            // the top bit of the code is set for events sent by a client rather than the server
+            let synthetic = ev.event_type & 0x80 != 0;
+
+            manager.unmap_notify(conn, ev.window, synthetic).await?;
+        }
+        // windows can be destroyed without being unmapped first
+        Event::DestroyNotify(ev) if manager.has_dock(ev.window) => {
+            manager.remove_dock(conn, ev.window).await?;
+        }
+        Event::DestroyNotify(ev) if manager.has_client(ev.window) => {
+            manager.remove_client(conn, ev.window).await?;
+        }
+        // substructure notify also gets us the frames being configured, so check the window
+        Event::ConfigureNotify(ev) if ev.window == root => {
+            manager.update_outputs(conn).await?;
+        }
+        Event::NoneOfTheAbove { opcode, .. } if manager.is_output_event(opcode) => {
+            manager.update_outputs(conn).await?;
+        }
+        Event::ButtonPress(ev) if ev.detail == 1 => {
+            if manager.has_tab_bar(ev.event) {
+                manager
+                    .click_tab_bar(conn, ev.event, ev.event_x, ev.event_y)
+                    .await?;
+            } else {
                 manager.set_focus(conn, ev.event).await?;
             }
         }
+        Event::Expose(ev) if ev.count == 0 => {
+            manager.expose_tab_bar(conn, ev.window).await?;
+        }
+        Event::ClientMessage(ev) => {
+            manager.client_message(conn, &ev).await?;
+        }
+        Event::PropertyNotify(ev) => {
+            manager.update_property(conn, ev.window, ev.atom).await?;
+        }
         Event::KeyPress(ev) => {
-            if let Some(k) = keyboard_state.process_keycode(ev.detail, ev.state) {
+            // binds are stored as the unmodified key plus a modifier mask, so look the key up
+            // without modifiers and compare the masks separately
+            if let Some(k) = keyboard_state.process_keycode(ev.detail, KeyButMask::default()) {
                 if let Some(c) = k.as_char() {
+                    // ignore lock, numlock and mouse buttons when matching modifiers
+                    let state = ev.state.inner & BIND_MODIFIERS;
+
                     for (&bind, action) in &CONFIG.binds {
-                        if bind.key == c {
+                        if bind.key == c && bind.mods.inner == state {
                             action.eval(manager, conn).await?;
                         }
                     }
@@ -39,7 +39,7 @@ impl Default for XcrabMsgConfig {
 
 fn load_file_inner() -> Result<XcrabConfig> {
     let home_dir = get_home();
-    let contents = std::fs::read_to_string(format!("{}/.config/xcrab/config.toml", home_dir))?;
+    let contents = std::fs::read_to_string(format!("{home_dir}/.config/xcrab/config.toml"))?;
     let config: XcrabConfig = toml::from_str(&contents)?;
     Ok(config)
 }
@@ -14,7 +14,9 @@
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use crate::x11::client::XcrabWindowManager;
-use crate::Result;
+use crate::x11::layout::LayoutKind;
+use crate::x11::tree::{Direction, PaneMode, ResizeAmount};
+use crate::{Result, XcrabError};
 use breadx::AsyncDisplay;
 use std::path::Path;
 use std::str::FromStr;
@@ -52,7 +54,7 @@ pub async fn listener_task<P: AsRef<Path>>(
 
         // we can unwrap here because if the channel is closed then something's not right
         if let Err(e) = result_recv.recv().await.unwrap() {
-            stream.write_all(format!("{}", e).as_bytes()).await?;
+            stream.write_all(format!("{e}").as_bytes()).await?;
         } else {
             stream.write_all(&[]).await?;
         }
@@ -81,6 +83,39 @@ pub async fn on_recv<Dpy: AsyncDisplay + ?Sized>(
 #[non_exhaustive]
 pub enum Action {
     Close,
+    Focus(Direction),
+    Move(Direction),
+    Swap(Direction),
+    Resize(Direction, ResizeAmount),
+    Balance,
+    Layout(PaneMode),
+    SetLayout(LayoutKind),
+    ToggleFloating,
+    Fullscreen,
+    Workspace(String),
+    MoveToWorkspace(String),
+    FocusOutput(Direction),
+    MoveToOutput(Direction),
+    MoveToScratchpad,
+    /// Shows or hides a scratchpad client, optionally picking it by `WM_CLASS`.
+    Scratchpad(Option<String>),
+}
+
+/// Parses the argument at `index`, reporting a missing argument as an error.
+fn parse_arg<T: FromStr<Err = XcrabError>>(parts: &[String], index: usize) -> Result<T> {
+    parts
+        .get(index)
+        .ok_or_else(|| XcrabError::from(format!("Missing argument for action: {}", parts[0])))?
+        .parse()
+}
+
+/// Joins everything from `index` onwards into a name, so names can contain spaces.
+fn parse_name(parts: &[String], index: usize) -> Result<String> {
+    if parts.len() <= index {
+        return Err(format!("Missing argument for action: {}", parts[0]).into());
+    }
+
+    Ok(parts[index..].join(" "))
 }
 
 impl FromStr for Action {
@@ -111,11 +146,44 @@ impl FromStr for Action {
             };
         }
 
-        // TODO: When more actions are added (such as focus etc), they will take arguments. In that
-        // case, they will get passed the rest of `parts`.
+        // actions that take arguments get passed the rest of `parts`
         eq_ignore_ascii_case_match!((parts[0]) {
             "close" => Ok(Close),
-            else => Err(format!("Unknown action: {}", s).into()),
+            "focus" => match parts.get(1).map(String::as_str) {
+                Some("output") => Ok(FocusOutput(parse_arg(&parts, 2)?)),
+                _ => Ok(Focus(parse_arg(&parts, 1)?)),
+            },
+            "move" => match parts.get(1).map(String::as_str) {
+                Some("to") => match parts.get(2).map(String::as_str) {
+                    Some("workspace") => Ok(MoveToWorkspace(parse_name(&parts, 3)?)),
+                    Some("output") => Ok(MoveToOutput(parse_arg(&parts, 3)?)),
+                    Some("scratchpad") => Ok(MoveToScratchpad),
+                    Some(s) => Err(format!("Unknown move target: {s}").into()),
+                    None => Err(String::from("Missing argument for action: move to").into()),
+                },
+                _ => Ok(Move(parse_arg(&parts, 1)?)),
+            },
+            "swap" => Ok(Swap(parse_arg(&parts, 1)?)),
+            "resize" => Ok(Resize(parse_arg(&parts, 1)?, parse_arg(&parts, 2)?)),
+            "balance" => Ok(Balance),
+            "fullscreen" => Ok(Fullscreen),
+            "workspace" => Ok(Workspace(parse_name(&parts, 1)?)),
+            "scratchpad" => match parts.get(1).map(String::as_str) {
+                Some("show") => Ok(Scratchpad(parse_name(&parts, 2).ok())),
+                Some(s) => Err(format!("Unknown scratchpad action: {s}").into()),
+                None => Err(String::from("Missing argument for action: scratchpad").into()),
+            },
+            "toggle" => match parts.get(1).map(String::as_str) {
+                Some("floating") => Ok(ToggleFloating),
+                Some(s) => Err(format!("Unknown toggle: {s}").into()),
+                None => Err(String::from("Missing argument for action: toggle").into()),
+            },
+            // `layout` covers both how the focused pane is shown and which layout places clients
+            "layout" => match parse_arg(&parts, 1) {
+                Ok(mode) => Ok(Layout(mode)),
+                Err(_) => Ok(SetLayout(parse_arg(&parts, 1)?)),
+            },
+            else => Err(format!("Unknown action: {s}").into()),
         })
     }
 }
@@ -131,6 +199,23 @@ impl Action {
 
         match self {
             Close => manager.destroy_focused_client(conn).await?,
+            Focus(direction) => manager.focus_direction(conn, *direction).await?,
+            Move(direction) => manager.move_focused(conn, *direction).await?,
+            Swap(direction) => manager.swap_focused(conn, *direction).await?,
+            Resize(direction, amount) => {
+                manager.resize_focused(conn, *direction, *amount).await?;
+            }
+            Balance => manager.balance(conn).await?,
+            Layout(mode) => manager.set_pane_mode(conn, *mode).await?,
+            SetLayout(layout) => manager.set_layout(conn, *layout).await?,
+            ToggleFloating => manager.toggle_floating(conn).await?,
+            Fullscreen => manager.toggle_fullscreen(conn).await?,
+            Workspace(name) => manager.switch_workspace(conn, name).await?,
+            MoveToWorkspace(name) => manager.move_focused_to_workspace(conn, name).await?,
+            FocusOutput(direction) => manager.focus_output(conn, *direction).await?,
+            MoveToOutput(direction) => manager.move_focused_to_output(conn, *direction).await?,
+            MoveToScratchpad => manager.move_focused_to_scratchpad(conn).await?,
+            Scratchpad(class) => manager.toggle_scratchpad(conn, class.as_deref()).await?,
         }
 
         Ok(())
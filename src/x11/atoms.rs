@@ -0,0 +1,79 @@
+// Copyright (C) 2022 Infoshock Tech
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use breadx::{prelude::AsyncDisplayXprotoExt, AsyncDisplay, Atom};
+
+use crate::Result;
+
+macro_rules! atoms {
+    ($($field:ident => $name:literal,)+) => {
+        /// Atoms that aren't predefined by the protocol, interned once at startup so we don't
+        /// have to do a round trip every time we need one.
+        #[derive(Debug, Clone, Copy, Default)]
+        pub struct Atoms {
+            $(pub $field: Atom,)+
+        }
+
+        impl Atoms {
+            pub async fn intern<Dpy: AsyncDisplay + ?Sized>(conn: &mut Dpy) -> Result<Self> {
+                Ok(Self {
+                    $($field: conn.intern_atom_immediate_async($name, false).await?,)+
+                })
+            }
+        }
+    };
+}
+
+atoms! {
+    utf8_string => "UTF8_STRING",
+    wm_state => "WM_STATE",
+    wm_protocols => "WM_PROTOCOLS",
+    wm_take_focus => "WM_TAKE_FOCUS",
+    wm_delete_window => "WM_DELETE_WINDOW",
+    wm_window_role => "WM_WINDOW_ROLE",
+    net_wm_ping => "_NET_WM_PING",
+    net_wm_pid => "_NET_WM_PID",
+    net_supported => "_NET_SUPPORTED",
+    net_supporting_wm_check => "_NET_SUPPORTING_WM_CHECK",
+    net_client_list => "_NET_CLIENT_LIST",
+    net_client_list_stacking => "_NET_CLIENT_LIST_STACKING",
+    net_active_window => "_NET_ACTIVE_WINDOW",
+    net_close_window => "_NET_CLOSE_WINDOW",
+    net_number_of_desktops => "_NET_NUMBER_OF_DESKTOPS",
+    net_current_desktop => "_NET_CURRENT_DESKTOP",
+    net_desktop_names => "_NET_DESKTOP_NAMES",
+    net_wm_desktop => "_NET_WM_DESKTOP",
+    net_frame_extents => "_NET_FRAME_EXTENTS",
+    net_wm_name => "_NET_WM_NAME",
+    net_wm_state => "_NET_WM_STATE",
+    net_wm_state_fullscreen => "_NET_WM_STATE_FULLSCREEN",
+    net_wm_window_type => "_NET_WM_WINDOW_TYPE",
+    net_wm_window_type_normal => "_NET_WM_WINDOW_TYPE_NORMAL",
+    net_wm_window_type_dialog => "_NET_WM_WINDOW_TYPE_DIALOG",
+    net_wm_window_type_splash => "_NET_WM_WINDOW_TYPE_SPLASH",
+    net_wm_window_type_utility => "_NET_WM_WINDOW_TYPE_UTILITY",
+    net_wm_window_type_toolbar => "_NET_WM_WINDOW_TYPE_TOOLBAR",
+    net_wm_window_type_menu => "_NET_WM_WINDOW_TYPE_MENU",
+    net_wm_window_type_dropdown_menu => "_NET_WM_WINDOW_TYPE_DROPDOWN_MENU",
+    net_wm_window_type_popup_menu => "_NET_WM_WINDOW_TYPE_POPUP_MENU",
+    net_wm_window_type_tooltip => "_NET_WM_WINDOW_TYPE_TOOLTIP",
+    net_wm_window_type_notification => "_NET_WM_WINDOW_TYPE_NOTIFICATION",
+    net_wm_window_type_combo => "_NET_WM_WINDOW_TYPE_COMBO",
+    net_wm_window_type_dnd => "_NET_WM_WINDOW_TYPE_DND",
+    net_wm_window_type_dock => "_NET_WM_WINDOW_TYPE_DOCK",
+    net_wm_window_type_desktop => "_NET_WM_WINDOW_TYPE_DESKTOP",
+    net_wm_strut => "_NET_WM_STRUT",
+    net_wm_strut_partial => "_NET_WM_STRUT_PARTIAL",
+}
@@ -15,213 +15,487 @@
 
 use breadx::auto::xproto::{KeyButMask, Keycode, Keysym};
 use breadx::{
-    auto::xproto::{ClientMessageEvent, InputFocus, SetInputFocusRequest},
+    auto::xproto::{
+        ClientMessageEvent, ConfigureNotifyEvent, ConfigureRequestEvent, InputFocus,
+        KillClientRequest, SetInputFocusRequest, StackMode,
+    },
     client_message_data::ClientMessageData,
-    prelude::{AsByteSequence, AsyncDisplayXprotoExt, PropertyType, SetMode},
+    prelude::{AsByteSequence, AsyncDisplayXprotoExt, MapState, SetMode},
     AsyncDisplay, AsyncDisplayExt, Atom, BreadError, ConfigureWindowParameters, ErrorCode, Event,
-    EventMask, KeyboardState, Window, WindowParameters, XidType,
+    EventMask, KeyboardState, Window, WindowParameters,
+};
+use std::{collections::HashMap, time::Instant};
+
+use super::{
+    atoms::Atoms,
+    hints::{FocusModel, Protocols, SizeHints},
+    layout::LayoutKind,
+    metadata::{Change, Metadata},
+    output::{query_outputs, select_output_events, Strut},
+    policy::{Layer, Manage, Policy},
+    property::{
+        get_atoms, get_client_machine, get_protocols, get_size_hints, get_strut, get_transient_for,
+        set_atoms, set_cardinals, set_strings, set_windows, set_wm_state, WmState, WM_NORMAL_HINTS,
+    },
+    rules::Actions,
+    tab_bar::{Tab, TabBar, TabPainter, TabStyle},
+    tree::{
+        Dimensions, Direction, PaneMode, Placements, RectangleContents, ResizeAmount, Spacing,
+        Tree, TreeSlot, XcrabKey,
+    },
 };
-use slotmap::{new_key_type, SlotMap};
-use std::{collections::HashMap, future::Future, pin::Pin, slice};
-
 use crate::{Result, XcrabError, CONFIG};
 
-#[derive(Debug, Clone, Copy)]
-pub enum Direction {
-    Up,
-    Down,
-    Left,
-    Right,
+impl From<Dimensions> for ConfigureWindowParameters {
+    fn from(dimensions: Dimensions) -> Self {
+        Self {
+            x: Some(dimensions.x.into()),
+            y: Some(dimensions.y.into()),
+            width: Some(dimensions.width.into()),
+            height: Some(dimensions.height.into()),
+            ..Default::default()
+        }
+    }
+}
+
+// the actions a `_NET_WM_STATE` message can ask for
+const NET_WM_STATE_REMOVE: u32 = 0;
+const NET_WM_STATE_ADD: u32 = 1;
+const NET_WM_STATE_TOGGLE: u32 = 2;
+
+#[derive(Debug, Clone, Default)]
+pub struct XcrabWindowManager {
+    clients: HashMap<Window, XcrabKey>,
+    tree: Tree<Client>,
+    workspaces: Vec<Workspace>,
+    /// The index of the focused workspace.
+    current: usize,
+    outputs: Vec<Output>,
+    /// The code of the first `RandR` event, if the server has `RandR`.
+    randr_event: Option<u8>,
+    /// Clients that are hidden away until they are summoned, oldest first.
+    scratchpad: Vec<Floating>,
+    /// Panels and the like, which we leave alone apart from keeping their struts free.
+    docks: HashMap<Window, Strut>,
+    /// Every client in the order they were mapped, for `_NET_CLIENT_LIST`.
+    client_order: Vec<Window>,
+    /// Every client from bottom to top, for `_NET_CLIENT_LIST_STACKING`. It follows every frame
+    /// we raise, and clients in the scratchpad keep their place until they're shown again.
+    stacking: Vec<Window>,
+    atoms: Atoms,
+    /// How many `UnmapNotify`s are on their way for each client because of something we did,
+    /// rather than the client withdrawing.
+    ignored_unmaps: HashMap<Window, usize>,
+    /// Clients we asked to close that haven't yet, with when we next check on them. Clients that
+    /// answered our ping are left to take their time.
+    closing: HashMap<Window, Option<Instant>>,
+    /// Our own off screen window, which has the focus when no client does.
+    wm_window: Option<Window>,
+    /// The time of the latest event that came with one, which `WM_TAKE_FOCUS` has to be sent
+    /// with.
+    time: u32,
+    /// The tab bars of tabbed and stacked panes, keyed by the pane.
+    tab_bars: HashMap<XcrabKey, TabBar>,
+    /// Created the first time a tab bar is drawn.
+    tab_painter: Option<TabPainter>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum Directionality {
-    Horizontal,
-    Vertical,
+/// A monitor, which shows one of the workspaces.
+#[derive(Debug, Clone, Copy)]
+struct Output {
+    dimensions: Dimensions,
+    workspace: usize,
 }
 
-#[derive(Debug, Clone, Copy, Default)]
-pub struct Dimensions {
-    x: u16,
-    y: u16,
-    width: u16,
-    height: u16,
+/// A separate set of clients with its own tree. Only one workspace is shown at a time, and the
+/// frames of the clients on the others are unmapped.
+#[derive(Debug, Clone)]
+struct Workspace {
+    name: String,
+    /// The root of the tree, or `None` if there are no tiled clients.
+    root: Option<XcrabKey>,
+    focused: Option<Window>,
+    layout: LayoutKind,
+    /// Clients that aren't part of the tree and are placed wherever they (or we) like.
+    floating: HashMap<Window, Floating>,
 }
 
-impl Dimensions {
-    fn split(self, direction: Directionality, count: usize) -> Vec<Self> {
-        let count_u16 = u16::try_from(count).unwrap();
-        match direction {
-            Directionality::Horizontal => {
-                let amount_for_windows = self.width - CONFIG.gap_size() * (count_u16 - 1);
-                let excess = amount_for_windows % count_u16;
-                let window_size = amount_for_windows / count_u16;
-                let window_stride = window_size + CONFIG.gap_size();
-
-                (0..count.try_into().unwrap())
-                    .map(|i| Dimensions {
-                        x: self.x + i * window_stride + if i < excess { 1 } else { 0 },
-                        width: window_size,
-                        ..self
-                    })
-                    .collect()
-            }
-            Directionality::Vertical => {
-                let amount_for_windows = self.height - CONFIG.gap_size() * (count_u16 - 1);
-                let excess = amount_for_windows % count_u16;
-                let window_size = amount_for_windows / count_u16;
-                let window_stride = window_size + CONFIG.gap_size();
-
-                (0..count.try_into().unwrap())
-                    .map(|i| Dimensions {
-                        y: self.y + i * window_stride + if i < excess { 1 } else { 0 },
-                        height: window_size,
-                        ..self
-                    })
-                    .collect()
-            }
+impl Workspace {
+    fn new(name: String) -> Self {
+        Self {
+            layout: CONFIG.layout(&name),
+            name,
+            root: None,
+            focused: None,
+            floating: HashMap::new(),
         }
     }
 }
 
-new_key_type!(
-    struct XcrabKey;
-);
+#[derive(Debug, Clone)]
+struct Client {
+    frame: FramedWindow,
+    metadata: Metadata,
+    /// Fullscreen clients cover the whole screen, on top of everything else. They keep their
+    /// place in the tree or floating layer so they can go back to it afterwards.
+    fullscreen: bool,
+    /// Where the frame was last put by the tree, so it isn't moved again if nothing changed.
+    applied: Option<Dimensions>,
+    /// Whether a rule already put the client on a workspace. That only happens once, so moving
+    /// it somewhere else afterwards sticks even if its title keeps matching.
+    sent_to_workspace: bool,
+}
 
-#[derive(Debug, Clone, Default)]
-pub struct XcrabWindowManager {
-    clients: HashMap<Window, XcrabKey>,
-    rects: SlotMap<XcrabKey, Rectangle>,
-    focused: Option<Window>,
+impl Client {
+    /// Whether either half of the client's `WM_CLASS` is `name`, ignoring case.
+    fn has_class(&self, name: &str) -> bool {
+        self.metadata.instance.eq_ignore_ascii_case(name)
+            || self.metadata.class.eq_ignore_ascii_case(name)
+    }
 }
 
 #[derive(Debug, Clone)]
-struct Rectangle {
-    parent: XcrabKey,
-    cached_dimensions: Dimensions,
-    contents: RectangleContents,
+struct Floating {
+    client: Client,
+    dimensions: Dimensions,
+    /// Where the client was in the tree before it was floated, so it can go back there.
+    slot: Option<TreeSlot>,
+    /// Whether the client came out of the scratchpad, and goes back there when it is toggled.
+    scratchpad: bool,
 }
 
-impl Rectangle {
-    fn unwrap_pane(&self) -> &Pane {
-        match &self.contents {
-            RectangleContents::Pane(pane) => pane,
-            RectangleContents::Client(_) => unreachable!(),
+impl XcrabWindowManager {
+    pub fn new(atoms: Atoms) -> Self {
+        XcrabWindowManager {
+            atoms,
+            workspaces: CONFIG
+                .workspaces()
+                .into_iter()
+                .map(Workspace::new)
+                .collect(),
+            ..Default::default()
         }
     }
 
-    fn unwrap_client(&self) -> &Client {
-        match &self.contents {
-            RectangleContents::Pane(_) => unreachable!(),
-            RectangleContents::Client(client) => client,
+    /// Finds the monitors and puts a workspace on each of them, focusing the first (primary) one.
+    pub async fn init_outputs<Dpy: AsyncDisplay + ?Sized>(&mut self, conn: &mut Dpy) -> Result<()> {
+        let areas = query_outputs(conn).await?;
+
+        // this has to come after `query_outputs`, which tells the server what version we speak
+        self.randr_event = select_output_events(conn).await?;
+
+        self.assign_outputs(areas);
+        self.current = self.outputs[0].workspace;
+
+        Ok(())
+    }
+
+    /// Remembers the `time` of an event, for the next time we ask a client to take the focus.
+    pub fn set_time(&mut self, time: u32) {
+        self.time = time;
+    }
+
+    /// Whether an event we don't otherwise know about is `RandR` telling us the monitors changed.
+    pub fn is_output_event(&self, opcode: u8) -> bool {
+        // `ScreenChangeNotify` and `Notify`, which covers crtcs and outputs
+        self.randr_event
+            .is_some_and(|first| opcode == first || opcode == first.wrapping_add(1))
+    }
+
+    /// Asks the server for the monitors again after they (or the root window) changed, and lays
+    /// everything out to fit them.
+    pub async fn update_outputs<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+    ) -> Result<()> {
+        let areas = query_outputs(conn).await?;
+
+        // a single change usually comes with a few events, only the first one does anything
+        if areas
+            .iter()
+            .eq(self.outputs.iter().map(|output| &output.dimensions))
+        {
+            return Ok(());
+        }
+
+        let before: Vec<usize> = self.outputs.iter().map(|output| output.workspace).collect();
+
+        self.assign_outputs(areas);
+
+        for &ws in &before {
+            if !self.is_shown(ws) {
+                self.set_workspace_mapped(conn, ws, false).await?;
+            }
+        }
+
+        for output in 0..self.outputs.len() {
+            let Output {
+                dimensions: area,
+                workspace: ws,
+            } = self.outputs[output];
+
+            // floating clients may have been left on a monitor that isn't there anymore
+            for floating in self.workspaces[ws].floating.values_mut() {
+                let Dimensions {
+                    x,
+                    y,
+                    width,
+                    height,
+                } = floating.dimensions;
+
+                if !area.contains(x, y) {
+                    floating.dimensions = area.center(width, height);
+                }
+            }
+
+            self.update_workspace(conn, ws).await?;
+
+            if !before.contains(&ws) {
+                self.set_workspace_mapped(conn, ws, true).await?;
+            }
         }
+
+        // there might be new workspaces for new outputs
+        self.update_desktops(conn).await?;
+        self.update_focused(conn).await
     }
 
-    fn unwrap_pane_mut(&mut self) -> &mut Pane {
-        match &mut self.contents {
-            RectangleContents::Pane(pane) => pane,
-            RectangleContents::Client(_) => unreachable!(),
+    /// Replaces the outputs with monitors covering `areas`. Monitors keep the workspace they
+    /// were showing, while the workspaces of monitors that went away are hidden, unless one of
+    /// them was focused, in which case it takes over the first monitor.
+    fn assign_outputs(&mut self, areas: Vec<Dimensions>) {
+        let mut shown: Vec<usize> = self
+            .outputs
+            .iter()
+            .map(|output| output.workspace)
+            .take(areas.len())
+            .collect();
+
+        if !self.outputs.is_empty() && !shown.contains(&self.current) {
+            shown[0] = self.current;
+        }
+
+        while shown.len() < areas.len() {
+            let hidden = (0..self.workspaces.len()).find(|ws| !shown.contains(ws));
+
+            // there might be more monitors than workspaces
+            let ws = hidden.unwrap_or_else(|| {
+                let name = (self.workspaces.len() + 1).to_string();
+                self.workspaces.push(Workspace::new(name));
+
+                self.workspaces.len() - 1
+            });
+
+            shown.push(ws);
         }
+
+        self.outputs = areas
+            .into_iter()
+            .zip(shown)
+            .map(|(dimensions, workspace)| Output {
+                dimensions,
+                workspace,
+            })
+            .collect();
+    }
+
+    /// The index of the output workspace `ws` is shown on, if it is shown.
+    fn output_of(&self, ws: usize) -> Option<usize> {
+        self.outputs
+            .iter()
+            .position(|output| output.workspace == ws)
+    }
+
+    /// Whether workspace `ws` is on screen.
+    fn is_shown(&self, ws: usize) -> bool {
+        self.output_of(ws).is_some()
+    }
+
+    /// The area of the output workspace `ws` is on. Hidden workspaces will be shown on the
+    /// focused output, so that's the one they get.
+    fn output_area(&self, ws: usize) -> Dimensions {
+        let output = self
+            .output_of(ws)
+            .or_else(|| self.output_of(self.current))
+            .unwrap_or(0);
+
+        self.outputs[output].dimensions
     }
 
-    fn unwrap_client_mut(&mut self) -> &mut Client {
-        match &mut self.contents {
-            RectangleContents::Pane(_) => unreachable!(),
-            RectangleContents::Client(client) => client,
+    /// The area covered by all of the outputs together.
+    fn screen_area(&self) -> Dimensions {
+        let right = self
+            .outputs
+            .iter()
+            .map(|output| u32::from(output.dimensions.x) + u32::from(output.dimensions.width))
+            .max()
+            .unwrap_or_default();
+        let bottom = self
+            .outputs
+            .iter()
+            .map(|output| u32::from(output.dimensions.y) + u32::from(output.dimensions.height))
+            .max()
+            .unwrap_or_default();
+
+        Dimensions {
+            x: 0,
+            y: 0,
+            width: u16::try_from(right).unwrap_or(u16::MAX),
+            height: u16::try_from(bottom).unwrap_or(u16::MAX),
         }
     }
-}
 
-#[derive(Debug, Clone)]
-enum RectangleContents {
-    Pane(Pane),
-    Client(Client),
-}
+    /// The area of the output workspace `ws` is on, minus the space kept free for docks.
+    fn usable_area(&self, ws: usize) -> Dimensions {
+        let screen = self.screen_area();
 
-#[derive(Debug, Clone)]
-struct Pane {
-    children: Vec<XcrabKey>,
-    directionality: Directionality,
-}
+        self.docks
+            .values()
+            .fold(self.output_area(ws), |area, strut| {
+                strut.reserve(area, screen)
+            })
+    }
 
-#[derive(Debug, Clone, Copy)]
-struct Client {
-    frame: FramedWindow,
-}
+    /// The area the tree of workspace `ws` gets, which is its output minus the docks and the
+    /// outer gaps.
+    fn tiling_area(&self, ws: usize) -> Dimensions {
+        let area = self.usable_area(ws);
+        let outer_gap_size = CONFIG.outer_gap_size();
 
-impl XcrabWindowManager {
-    pub fn new() -> Self {
-        XcrabWindowManager::default()
+        Dimensions {
+            x: area.x.saturating_add(outer_gap_size),
+            y: area.y.saturating_add(outer_gap_size),
+            width: area.width.saturating_sub(outer_gap_size.saturating_mul(2)),
+            height: area.height.saturating_sub(outer_gap_size.saturating_mul(2)),
+        }
     }
 
-    /// Given the `rect_key` from a `parent -> rect` relationship, makes A
-    /// `parent -> new_pane -> rect` relationship, then returns `new_pane_key`
-    fn insert_pane_above(
-        &mut self,
-        rect_key: XcrabKey,
-        directionality: Directionality,
-    ) -> Option<XcrabKey> {
-        let rect = self.rects.get(rect_key)?;
-        let rect_dimensions = rect.cached_dimensions;
-        let parent_key = rect.parent;
+    fn workspace(&self) -> &Workspace {
+        &self.workspaces[self.current]
+    }
 
-        let new_pane = Rectangle {
-            parent: parent_key,
-            cached_dimensions: rect_dimensions,
-            contents: RectangleContents::Pane(Pane {
-                children: vec![rect_key],
-                directionality,
-            }),
-        };
+    fn workspace_mut(&mut self) -> &mut Workspace {
+        &mut self.workspaces[self.current]
+    }
 
-        let new_pane_key = if parent_key == rect_key {
-            // the given node was the root node
+    /// The focused client on the current workspace.
+    fn focused(&self) -> Option<Window> {
+        self.workspace().focused
+    }
 
-            // this new pane will be the new root, so it becomes its own parent
-            self.rects.insert_with_key(|key| Rectangle {
-                parent: key,
-                ..new_pane
-            })
-        } else {
-            // the given node was not the root node, and thus has a parent
+    /// The index of the workspace called `name`, which is created if it doesn't exist yet.
+    /// Actions are lowercased before we see them, so names are compared case-insensitively.
+    fn workspace_index(&mut self, name: &str) -> usize {
+        if let Some(index) = self
+            .workspaces
+            .iter()
+            .position(|ws| ws.name.eq_ignore_ascii_case(name))
+        {
+            return index;
+        }
 
-            let new_pane_key = self.rects.insert(new_pane);
+        self.workspaces.push(Workspace::new(name.to_string()));
+        self.workspaces.len() - 1
+    }
 
-            let parent_pane = self.rects.get_mut(parent_key).unwrap().unwrap_pane_mut();
-            let index = parent_pane
-                .children
-                .iter()
-                .copied()
-                .position(|v| v == rect_key)
-                .unwrap();
-            // replace the "parent -> rect" relationship with a "parent -> new_pane" relationship
-            parent_pane.children[index] = new_pane_key;
+    /// The index of the workspace `win` is on, if it is one of our clients.
+    fn workspace_of(&self, win: Window) -> Option<usize> {
+        if let Some(&key) = self.clients.get(&win) {
+            let root = self.tree.root_of(key);
+            return self.workspaces.iter().position(|ws| ws.root == Some(root));
+        }
 
-            new_pane_key
-        };
+        self.workspaces
+            .iter()
+            .position(|ws| ws.floating.contains_key(&win))
+    }
+
+    fn find_floating(&self, win: Window) -> Option<&Floating> {
+        self.workspaces.iter().find_map(|ws| ws.floating.get(&win))
+    }
+
+    fn find_floating_mut(&mut self, win: Window) -> Option<&mut Floating> {
+        self.workspaces
+            .iter_mut()
+            .find_map(|ws| ws.floating.get_mut(&win))
+    }
+
+    /// The index of the workspace whose tree `key` is in.
+    fn workspace_of_key(&self, key: XcrabKey) -> usize {
+        let root = self.tree.root_of(key);
+
+        self.workspaces
+            .iter()
+            .position(|ws| ws.root == Some(root))
+            .unwrap_or(self.current)
+    }
 
-        let rect = self.rects.get_mut(rect_key).unwrap();
-        rect.parent = new_pane_key;
+    /// Frames `win` with the border `actions` ask for, keeping `metadata` for it.
+    async fn new_client<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        win: Window,
+        actions: &Actions,
+        metadata: Metadata,
+    ) -> Result<Client> {
+        // reparenting a window that is already mapped unmaps it, which the client didn't ask for
+        let attrs = win.window_attributes_immediate_async(conn).await?;
+
+        if attrs.map_state != MapState::Unmapped {
+            self.ignore_unmap(win);
+        }
 
-        Some(new_pane_key)
+        let mut frame = frame(conn, &self.atoms, win, &metadata).await?;
+        frame.border_size = actions.border_size.unwrap_or(frame.border_size);
+        frame.border_color = actions.border_color.unwrap_or(frame.border_color);
+
+        let border = frame.border_size.into();
+        may_not_exist(set_cardinals(conn, win, self.atoms.net_frame_extents, &[border; 4]).await)?;
+        self.client_order.push(win);
+        // new windows start out on top of their siblings
+        self.stacking.push(win);
+
+        // some clients ask to be fullscreen before they are even mapped
+        let fullscreen = get_atoms(conn, win, self.atoms.net_wm_state)
+            .await?
+            .contains(&self.atoms.net_wm_state_fullscreen);
+
+        Ok(Client {
+            frame,
+            metadata,
+            fullscreen,
+            applied: None,
+            sent_to_workspace: actions.workspace.is_some(),
+        })
     }
 
+    /// Focuses a client that was just put into the tree of workspace `ws`, and shows it if that
+    /// workspace is the current one.
     async fn focus_update_map<Dpy: AsyncDisplay + ?Sized>(
         &mut self,
         conn: &mut Dpy,
+        ws: usize,
         frame: FramedWindow,
         parent_key: XcrabKey,
     ) -> Result<()> {
         let win = frame.win;
 
         // we cant `set_focus` here since `win` isnt yet mapped
-        self.focused = Some(win);
+        self.workspaces[ws].focused = Some(win);
+        self.tree.mark_active(self.clients[&win]);
+
+        if !self.is_shown(ws) {
+            // it'll get laid out and mapped once its workspace is shown
+            return Ok(());
+        }
 
         self.update_rectangle(conn, parent_key, None).await?;
 
-        frame.map(conn).await?;
+        frame.map(conn, &self.atoms).await?;
 
-        self.update_focused(conn).await?;
+        if ws == self.current {
+            self.update_focused(conn).await?;
+        }
 
         Ok(())
     }
@@ -231,394 +505,2264 @@ impl XcrabWindowManager {
         &mut self,
         conn: &mut Dpy,
     ) -> Result<()> {
+        let root = conn.default_root();
+        let fallback = self.wm_window.unwrap_or(root);
+        let frame = self
+            .focused()
+            .and_then(|win| self.client(win))
+            .map(|client| client.frame);
+
+        // clients that don't want the focus from us still shouldn't leave it with the last one
+        let focus = frame
+            .filter(|frame| frame.focus.gets_focus())
+            .map_or(fallback, |frame| frame.win);
+
         // unfortunately, i cannot find a method on `conn` to set the focus.
 
         // https://www.x.org/releases/current/doc/xproto/x11protocol.html#Encoding::Requests
-        let mut req = SetInputFocusRequest {
+        let req = SetInputFocusRequest {
             req_type: 42, // constant, specified in x protocol docs.
-            revert_to: InputFocus::None,
-            length: 3,                  // constant, specified in x protocol docs.
-            focus: Window::from_xid(0), // None
-            time: 0,                    // CurrentTime
+            // if the client goes away before we hear about it, the keyboard still goes somewhere
+            revert_to: InputFocus::PointerRoot,
+            length: 3, // constant, specified in x protocol docs.
+            focus,
+            // `CurrentTime`. the last event we saw isn't necessarily the one that made us change
+            // the focus, and the server ignores the request if it's older than the last change
+            time: 0,
         };
 
-        if let Some(focus) = self.focused {
-            req.focus = focus;
+        conn.exchange_request_async(req).await?;
+
+        if let Some(frame) = frame.filter(|frame| frame.focus.takes_focus()) {
+            frame
+                .send_protocol(conn, &self.atoms, self.atoms.wm_take_focus, self.time)
+                .await?;
         }
 
-        conn.exchange_request_async(req).await?;
+        let active = frame.map_or(Window::const_from_xid(0), |frame| frame.win);
+        set_windows(conn, root, self.atoms.net_active_window, &[active]).await?;
 
         Ok(())
     }
 
-    /// Adds a new client.
+    /// Sets up the root window properties that tell other programs an EWMH window manager is
+    /// running, and which parts of the EWMH we support.
+    pub async fn init_ewmh<Dpy: AsyncDisplay + ?Sized>(&mut self, conn: &mut Dpy) -> Result<()> {
+        let root = conn.default_root();
+        let atoms = self.atoms;
+
+        // a window of our own that points to itself, so it can be told apart from one left
+        // behind by a window manager that went away
+        let check = conn
+            .create_simple_window_async(root, -1, -1, 1, 1, 0, 0, 0)
+            .await?;
+
+        set_windows(conn, root, atoms.net_supporting_wm_check, &[check]).await?;
+        set_windows(conn, check, atoms.net_supporting_wm_check, &[check]).await?;
+        set_strings(conn, &atoms, check, atoms.net_wm_name, &["xcrab"]).await?;
+
+        // the focus goes here when no client has it, which only works for a mapped window. it's
+        // off screen, and override-redirect so that nobody (us included) tries to manage it
+        check
+            .change_attributes_async(
+                conn,
+                WindowParameters {
+                    override_redirect: Some(1),
+                    ..Default::default()
+                },
+            )
+            .await?;
+        check.map_async(conn).await?;
+
+        self.wm_window = Some(check);
+
+        let supported = [
+            atoms.net_supported,
+            atoms.net_supporting_wm_check,
+            atoms.net_client_list,
+            atoms.net_client_list_stacking,
+            atoms.net_active_window,
+            atoms.net_close_window,
+            atoms.net_wm_ping,
+            atoms.net_number_of_desktops,
+            atoms.net_current_desktop,
+            atoms.net_desktop_names,
+            atoms.net_wm_desktop,
+            atoms.net_frame_extents,
+            atoms.net_wm_name,
+            atoms.net_wm_state,
+            atoms.net_wm_state_fullscreen,
+            atoms.net_wm_strut,
+            atoms.net_wm_strut_partial,
+            atoms.net_wm_window_type,
+            atoms.net_wm_window_type_normal,
+            atoms.net_wm_window_type_dialog,
+            atoms.net_wm_window_type_splash,
+            atoms.net_wm_window_type_utility,
+            atoms.net_wm_window_type_toolbar,
+            atoms.net_wm_window_type_menu,
+            atoms.net_wm_window_type_dropdown_menu,
+            atoms.net_wm_window_type_popup_menu,
+            atoms.net_wm_window_type_tooltip,
+            atoms.net_wm_window_type_notification,
+            atoms.net_wm_window_type_combo,
+            atoms.net_wm_window_type_dnd,
+            atoms.net_wm_window_type_dock,
+            atoms.net_wm_window_type_desktop,
+        ];
+
+        set_atoms(conn, root, atoms.net_supported, &supported).await?;
+
+        self.update_desktops(conn).await?;
+        self.update_client_list(conn).await?;
+        self.update_focused(conn).await
+    }
+
+    /// Publishes the workspaces, and which one is focused, on the root window.
+    async fn update_desktops<Dpy: AsyncDisplay + ?Sized>(&self, conn: &mut Dpy) -> Result<()> {
+        let root = conn.default_root();
+        let names: Vec<&str> = self.workspaces.iter().map(|ws| ws.name.as_str()).collect();
+        let count = u32::try_from(names.len()).unwrap_or(u32::MAX);
+        let current = u32::try_from(self.current).unwrap_or(u32::MAX);
+
+        set_cardinals(conn, root, self.atoms.net_number_of_desktops, &[count]).await?;
+        set_strings(
+            conn,
+            &self.atoms,
+            root,
+            self.atoms.net_desktop_names,
+            &names,
+        )
+        .await?;
+        set_cardinals(conn, root, self.atoms.net_current_desktop, &[current]).await?;
+
+        Ok(())
+    }
+
+    /// Publishes every client on the root window, in the order they were mapped and in the order
+    /// they're stacked.
+    async fn update_client_list<Dpy: AsyncDisplay + ?Sized>(&self, conn: &mut Dpy) -> Result<()> {
+        let root = conn.default_root();
+
+        set_windows(conn, root, self.atoms.net_client_list, &self.client_order).await?;
+        self.update_stacking(conn).await
+    }
+
+    /// Publishes the order the clients are stacked in on the root window.
+    async fn update_stacking<Dpy: AsyncDisplay + ?Sized>(&self, conn: &mut Dpy) -> Result<()> {
+        let root = conn.default_root();
+
+        set_windows(
+            conn,
+            root,
+            self.atoms.net_client_list_stacking,
+            &self.stacking,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Remembers that the frame of `win` was just raised above everything else.
+    fn raised(&mut self, win: Window) {
+        self.stacking.retain(|&client| client != win);
+        self.stacking.push(win);
+    }
+
+    /// Tells `win` which workspace it's on, whenever it's put on a different one.
+    async fn set_desktop<Dpy: AsyncDisplay + ?Sized>(
+        &self,
+        conn: &mut Dpy,
+        win: Window,
+        ws: usize,
+    ) -> Result<()> {
+        let desktop = u32::try_from(ws).unwrap_or(u32::MAX);
+
+        may_not_exist(set_cardinals(conn, win, self.atoms.net_wm_desktop, &[desktop]).await)?;
+
+        Ok(())
+    }
+
+    /// Decides what to do with `win` based on its window type, the config, and the rules that
+    /// match it. The actions of those rules are returned for `add_client` to carry out, along
+    /// with the metadata they were matched against so it doesn't have to be read again.
+    pub async fn window_policy<Dpy: AsyncDisplay + ?Sized>(
+        &self,
+        conn: &mut Dpy,
+        win: Window,
+    ) -> Result<(Policy, Actions, Metadata)> {
+        let metadata = Metadata::read(conn, &self.atoms, win).await?;
+        let ty = metadata.window_type(&self.atoms);
+        let actions = Actions::of(CONFIG.rules(), &metadata, ty, false);
+
+        Ok((actions.adjust(CONFIG.window_policy(ty)), actions, metadata))
+    }
+
+    /// Adds a new client, tiling or floating it depending on `policy`, and putting it where
+    /// `actions` say.
     pub async fn add_client<Dpy: AsyncDisplay + ?Sized>(
         &mut self,
         conn: &mut Dpy,
         win: Window,
+        policy: Policy,
+        actions: &Actions,
+        metadata: Metadata,
     ) -> Result<()> {
-        // use rand::prelude::SliceRandom;
-        // let direction = *[
-        //     Direction::Up,
-        //     Direction::Down,
-        //     Direction::Left,
-        //     Direction::Right,
-        // ]
-        // .choose(&mut rand::thread_rng())
-        // .unwrap();
-        self.add_client_direction(conn, win, Direction::Right).await
+        let ws = match &actions.workspace {
+            Some(name) => self.workspace_index(name),
+            None => self.current,
+        };
+
+        let client = self.new_client(conn, win, actions, metadata).await?;
+        let focused = self.workspace().focused;
+
+        if !self.is_shown(ws) {
+            // showing a workspace only maps the frames, so the client itself has to be mapped now
+            may_not_exist(win.map_async(conn).await)?;
+            client
+                .frame
+                .set_state(conn, &self.atoms, WmState::Iconic)
+                .await?;
+        }
+
+        if policy.manage == Manage::Float {
+            let geometry = win.geometry_immediate_async(conn).await?;
+            let border = client.frame.border_size.saturating_mul(2);
+
+            let parent = get_transient_for(conn, win)
+                .await?
+                .and_then(|parent| self.client_dimensions(parent));
+
+            let dimensions = policy.place(
+                (
+                    geometry.width.saturating_add(border),
+                    geometry.height.saturating_add(border),
+                ),
+                (
+                    u16::try_from(geometry.x).unwrap_or(0),
+                    u16::try_from(geometry.y).unwrap_or(0),
+                ),
+                self.usable_area(ws),
+                parent,
+            );
+
+            self.add_floating(conn, ws, client, dimensions, None)
+                .await?;
+        } else {
+            let direction = actions.split.unwrap_or(Direction::Right);
+
+            self.tile_client(conn, ws, client, direction).await?;
+        }
+
+        if actions.fullscreen == Some(true) {
+            self.set_fullscreen(conn, win, true).await?;
+        }
+
+        self.set_desktop(conn, win, ws).await?;
+        self.update_client_list(conn).await?;
+
+        if actions.workspace.is_some() {
+            // the workspace might not have existed before
+            self.update_desktops(conn).await?;
+        }
+
+        // give focus back to whoever had it before
+        match focused {
+            Some(focused) if !policy.focus && self.has_client(focused) => {
+                self.set_focus(conn, focused).await
+            }
+            _ => Ok(()),
+        }
     }
 
-    /// Adds a new client in the given direction from the focused window.
-    pub async fn add_client_direction<Dpy: AsyncDisplay + ?Sized>(
+    /// Maps `win` without a frame and leaves it alone after putting it where `policy` says.
+    pub async fn add_unmanaged<Dpy: AsyncDisplay + ?Sized>(
         &mut self,
         conn: &mut Dpy,
         win: Window,
-        direction: Direction,
+        policy: Policy,
     ) -> Result<()> {
-        #[allow(clippy::enum_glob_use)]
-        use {Direction::*, Directionality::*};
-
-        let focused = match self.focused {
-            Some(v) => v,
-            None => return self.add_first_client(conn, win).await,
+        let geometry = win.geometry_immediate_async(conn).await?;
+
+        let dimensions = policy.place(
+            (geometry.width, geometry.height),
+            (
+                u16::try_from(geometry.x).unwrap_or(0),
+                u16::try_from(geometry.y).unwrap_or(0),
+            ),
+            self.usable_area(self.current),
+            None,
+        );
+
+        let stack_mode = match policy.layer {
+            Layer::Normal => None,
+            Layer::Above => Some(StackMode::Above),
+            Layer::Below => Some(StackMode::Below),
         };
 
-        // this code path is somewhat difficult to understand, so i added some comments
+        may_not_exist(
+            win.configure_async(
+                conn,
+                ConfigureWindowParameters {
+                    stack_mode,
+                    ..dimensions.into()
+                },
+            )
+            .await,
+        )?;
+        may_not_exist(win.map_async(conn).await)?;
 
-        // frame the window
-        let frame = frame(conn, win).await?;
+        Ok(())
+    }
 
+    /// Puts an already framed client into the tree of workspace `ws` in the given direction from
+    /// the focused window.
+    async fn tile_client<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        ws: usize,
+        client: Client,
+        direction: Direction,
+    ) -> Result<()> {
         // the XcrabKey to the focused client
-        let focused_client_key = *self
-            .clients
-            .get(&focused)
-            .ok_or(XcrabError::ClientDoesntExist)?;
-
-        // the directionality we want to find: if we are tiling Up or Down, we
-        // want a Vertical pane, and for Left or Right we want a Horizontal one.
-        let target_directionality = match direction {
-            Up | Down => Vertical,
-            Left | Right => Horizontal,
+        let Some(focused_client_key) = self.tiled_focus(ws) else {
+            return self.add_first_client(conn, ws, client).await;
         };
 
-        // this var will be used in the upcoming loop
-        let mut child_key = focused_client_key;
+        let frame = client.frame;
 
-        // go up the chain (using `Rectangle.parent`) until you find a pane with the correct directionality
-        let parent_key = loop {
-            let parent_key = self.rects.get(child_key).unwrap().parent;
+        let key = self.tree.insert_near(
+            focused_client_key,
+            client,
+            direction,
+            &mut self.workspaces[ws].root,
+        );
 
-            if parent_key == child_key {
-                // uh oh, we hit the top, now we will wrap the root client
-                // in a new pane and make this new pane the root
+        self.clients.insert(frame.win, key);
 
-                break self
-                    .insert_pane_above(child_key, target_directionality)
-                    .unwrap();
-            }
+        self.focus_update_map(conn, ws, frame, self.tree[key].parent)
+            .await?;
 
-            let parent = self.rects.get(parent_key).unwrap();
+        Ok(())
+    }
 
-            if parent.unwrap_pane().directionality == target_directionality {
-                // yay! found it
-                break parent_key;
-            }
+    /// Makes `client` the root of the (empty) tree of workspace `ws`.
+    async fn add_first_client<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        ws: usize,
+        client: Client,
+    ) -> Result<()> {
+        let frame = client.frame;
+
+        let key = self.tree.insert_root(client, self.tiling_area(ws));
 
-            // nope, continue
-            child_key = parent_key;
+        self.clients.insert(frame.win, key);
+        self.workspaces[ws].root = Some(key);
+
+        self.focus_update_map(conn, ws, frame, key).await?;
+
+        Ok(())
+    }
+
+    /// Puts an already framed client into the floating layer of workspace `ws` and focuses it.
+    async fn add_floating<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        ws: usize,
+        client: Client,
+        dimensions: Dimensions,
+        slot: Option<TreeSlot>,
+    ) -> Result<()> {
+        let floating = Floating {
+            client,
+            dimensions,
+            slot,
+            scratchpad: false,
         };
 
-        // `parent_key` now holds the key for the pane with the target
-        // directionality, and `child_key` holds the child key which will
-        // be used to find where to insert our new client
+        self.insert_floating(conn, ws, floating).await
+    }
 
-        // the key to the newly created client
-        let new_rect_key = self.rects.insert(Rectangle {
-            parent: parent_key,
-            // this default will be overriden by the `update_rectangle` down below
-            cached_dimensions: Dimensions::default(),
-            contents: RectangleContents::Client(Client { frame }),
-        });
+    /// Puts `floating` into the floating layer of workspace `ws` and focuses it.
+    async fn insert_floating<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        ws: usize,
+        floating: Floating,
+    ) -> Result<()> {
+        let frame = floating.client.frame;
+        let workspace = &mut self.workspaces[ws];
 
-        // the Pane of the Rectangle of `parent_key`
-        let parent_pane = self.rects.get_mut(parent_key).unwrap().unwrap_pane_mut();
+        workspace.floating.insert(frame.win, floating);
+        workspace.focused = Some(frame.win);
 
-        // the index which we want to `insert` at, found using `child_key`
-        let mut index = parent_pane
-            .children
-            .iter()
-            .copied()
-            .position(|v| v == child_key)
-            .unwrap();
+        if !self.is_shown(ws) {
+            return Ok(());
+        }
+
+        self.update_workspace(conn, ws).await?;
+
+        frame.map(conn, &self.atoms).await?;
+
+        if ws == self.current {
+            self.update_focused(conn).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Makes every fullscreen client on workspace `ws` cover its output and raises them above
+    /// everything else.
+    async fn update_fullscreen<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        ws: usize,
+    ) -> Result<()> {
+        let workspace = &self.workspaces[ws];
+        let leaves = workspace.root.map(|root| self.tree.leaves(root));
 
-        if let Down | Right = direction {
-            index += 1;
+        let frames: Vec<FramedWindow> = leaves
+            .iter()
+            .flatten()
+            .map(|&key| self.tree[key].unwrap_client())
+            .chain(workspace.floating.values().map(|floating| &floating.client))
+            .filter(|client| client.fullscreen)
+            .map(|client| client.frame)
+            .collect();
+
+        let area = self.output_area(ws);
+
+        for frame in frames {
+            frame.configure_fullscreen(conn, area).await?;
+            self.raised(frame.win);
         }
 
-        // insert the new rect
-        parent_pane.children.insert(index, new_rect_key);
+        // nothing gets restacked after this in any layout, so the order is final
+        self.update_stacking(conn).await
+    }
+
+    /// The tiled client on workspace `ws` that new clients are placed next to: the focused one,
+    /// or if a floating client has focus, the one that was focused last.
+    fn tiled_focus(&self, ws: usize) -> Option<XcrabKey> {
+        let workspace = &self.workspaces[ws];
 
-        self.clients.insert(win, new_rect_key);
+        if let Some(&key) = workspace.focused.and_then(|win| self.clients.get(&win)) {
+            return Some(key);
+        }
+
+        workspace
+            .root
+            .and_then(|root| self.active_leaf(root))
+            .and_then(|win| self.clients.get(&win))
+            .copied()
+    }
+
+    /// The root of the current workspace's tree, if it has any tiled clients.
+    fn tree_root(&self) -> Option<XcrabKey> {
+        self.workspace().root
+    }
+
+    /// Where `win` is on screen, if it is one of our clients.
+    fn client_dimensions(&self, win: Window) -> Option<Dimensions> {
+        match self.clients.get(&win) {
+            Some(&key) => Some(self.tree[key].cached_dimensions),
+            None => self.find_floating(win).map(|floating| floating.dimensions),
+        }
+    }
+
+    pub fn has_dock(&self, win: Window) -> bool {
+        self.docks.contains_key(&win)
+    }
+
+    /// Maps the dock `win` as it is, without a frame, and keeps its struts free from now on.
+    pub async fn add_dock<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        win: Window,
+    ) -> Result<()> {
+        // so we hear about its struts changing
+        win.set_event_mask_async(conn, EventMask::PROPERTY_CHANGE)
+            .await?;
+        may_not_exist(win.map_async(conn).await)?;
+
+        self.docks.insert(win, Strut::default());
+
+        self.update_dock(conn, win).await
+    }
+
+    /// Forgets about the dock `win`, giving its space back.
+    pub async fn remove_dock<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        win: Window,
+    ) -> Result<()> {
+        if self
+            .docks
+            .remove(&win)
+            .is_some_and(|strut| strut != Strut::default())
+        {
+            self.update_shown(conn).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Re-reads the struts of the dock `win`, laying everything out again if they changed.
+    async fn update_dock<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        win: Window,
+    ) -> Result<()> {
+        let strut = get_strut(conn, &self.atoms, win).await?;
+
+        if self.docks.insert(win, strut) != Some(strut) {
+            self.update_shown(conn).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Lays out every workspace that is on screen.
+    async fn update_shown<Dpy: AsyncDisplay + ?Sized>(&mut self, conn: &mut Dpy) -> Result<()> {
+        let shown: Vec<usize> = self.outputs.iter().map(|output| output.workspace).collect();
+
+        for ws in shown {
+            self.update_workspace(conn, ws).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Lays out the whole tree of the current workspace as well as its floating layer.
+    async fn update_all<Dpy: AsyncDisplay + ?Sized>(&mut self, conn: &mut Dpy) -> Result<()> {
+        self.update_workspace(conn, self.current).await
+    }
+
+    /// Lays out everything on workspace `ws`, which should be on screen.
+    async fn update_workspace<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        ws: usize,
+    ) -> Result<()> {
+        if let Some(root) = self.workspaces[ws].root {
+            // the workspace may have moved to a different output since it was last laid out
+            self.tree[root].cached_dimensions = self.tiling_area(ws);
+
+            self.update_rectangle(conn, root, None).await
+        } else {
+            self.free_stale_tab_bars(conn).await?;
+
+            self.update_floating(conn, ws).await?;
+            self.update_fullscreen(conn, ws).await
+        }
+    }
+
+    /// Moves every floating client on workspace `ws` to where it should be and raises them above
+    /// the tree, with the focused one on top.
+    async fn update_floating<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        ws: usize,
+    ) -> Result<()> {
+        let focused = self.focused().unwrap_or(Window::const_from_xid(0));
+
+        let mut floating: Vec<&Floating> = self.workspaces[ws]
+            .floating
+            .values()
+            .filter(|floating| !floating.client.fullscreen)
+            .collect();
+        floating.sort_by_key(|floating| floating.client.frame.win == focused);
+
+        let mut raised = Vec::new();
+
+        for floating in floating {
+            let frame = floating.client.frame;
+            raised.push(frame.win);
+
+            frame
+                .configure(conn, floating.dimensions, false, focused)
+                .await?;
+
+            frame
+                .frame
+                .configure_async(
+                    conn,
+                    ConfigureWindowParameters {
+                        stack_mode: Some(StackMode::Above),
+                        ..Default::default()
+                    },
+                )
+                .await?;
+        }
+
+        for win in raised {
+            self.raised(win);
+        }
+
+        Ok(())
+    }
+
+    /// Lays out the rectangle at `key` with `Tree::layout`, or its whole workspace with
+    /// `Tree::arrange` in the automatic layouts, and moves the frames to match.
+    async fn update_rectangle<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        key: XcrabKey,
+        dimensions: Option<Dimensions>,
+    ) -> Result<()> {
+        // every change to the tree ends up here, so this is where we catch any that broke it
+        #[cfg(debug_assertions)]
+        self.check_tree();
+
+        let ws = self.workspace_of_key(key);
+        let spacing = Spacing::from_config();
+
+        let placements = if let Some(layout) = self.workspaces[ws].layout.layout() {
+            // in monocle everything overlaps, so make sure the focused client is the one on top
+            let focused = self.workspaces[ws]
+                .focused
+                .and_then(|win| self.clients.get(&win))
+                .copied();
+
+            // automatic layouts always place every client, so start from the root
+            self.tree
+                .arrange(self.tree.root_of(key), &*layout, spacing, focused)
+        } else {
+            self.tree.layout(key, dimensions, spacing)
+        };
+
+        self.apply(conn, placements).await?;
+
+        self.free_stale_tab_bars(conn).await?;
+
+        // anything we just raised shouldn't end up above the floating or fullscreen clients
+        self.update_floating(conn, ws).await?;
+        self.update_fullscreen(conn, ws).await?;
+
+        Ok(())
+    }
+
+    /// Moves the frames and tab bars to where `placements` says they go. Clients that are already
+    /// in the right place only get their border redrawn, since that depends on the focus.
+    async fn apply<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        placements: Placements,
+    ) -> Result<()> {
+        let focused = self.focused().unwrap_or(Window::const_from_xid(0));
+
+        for (key, dimensions) in placements.clients {
+            let client = self.tree[key].unwrap_client_mut();
+            let frame = client.frame;
+
+            if client.fullscreen {
+                // taken care of by `update_fullscreen`, and put back in full once it's over
+                client.applied = None;
+            } else if client.applied == Some(dimensions) {
+                frame.set_border(conn, focused).await?;
+            } else {
+                client.applied = Some(dimensions);
+                frame.configure(conn, dimensions, true, focused).await?;
+            }
+        }
+
+        for (key, dimensions) in placements.tab_bars {
+            let style = TabStyle::of(self.tree[key].unwrap_pane().shown_mode()).unwrap();
+
+            self.update_tab_bar(conn, key, dimensions, style).await?;
+        }
+
+        for key in placements.raise {
+            let (window, client) = match &self.tree[key].contents {
+                RectangleContents::Client(client) => (client.frame.frame, Some(client.frame.win)),
+                RectangleContents::Pane(_) => match self.tab_bars.get(&key) {
+                    Some(tab_bar) => (tab_bar.window, None),
+                    None => continue,
+                },
+            };
+
+            window
+                .configure_async(
+                    conn,
+                    ConfigureWindowParameters {
+                        stack_mode: Some(StackMode::Above),
+                        ..Default::default()
+                    },
+                )
+                .await?;
+
+            if let Some(win) = client {
+                self.raised(win);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether `key` is shown, i.e. it isn't in a hidden tab of a tabbed or stacked pane or
+    /// covered up by the focused client in monocle.
+    fn is_visible(&self, key: XcrabKey) -> bool {
+        let workspace = &self.workspaces[self.workspace_of_key(key)];
+
+        match workspace.layout {
+            LayoutKind::Tree => self.tree.is_visible(key),
+            LayoutKind::Monocle => workspace
+                .focused
+                .and_then(|win| self.clients.get(&win))
+                .is_some_and(|&focused| self.tree.is_ancestor(key, focused)),
+            _ => true,
+        }
+    }
+
+    /// The window of `Tree::active_leaf`.
+    fn active_leaf(&self, key: XcrabKey) -> Option<Window> {
+        let leaf = self.tree.active_leaf(key)?;
+
+        Some(self.tree[leaf].unwrap_client().frame.win)
+    }
+
+    /// The title shown on the tab for `key`, which for panes is that of their active client.
+    fn tab_title(&self, key: XcrabKey) -> &str {
+        self.tree.active_leaf(key).map_or("", |leaf| {
+            self.tree[leaf].unwrap_client().metadata.title.as_str()
+        })
+    }
+
+    /// Creates or moves the tab bar of the pane at `key`, then redraws it.
+    async fn update_tab_bar<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        key: XcrabKey,
+        dimensions: Dimensions,
+        style: TabStyle,
+    ) -> Result<()> {
+        if let Some(tab_bar) = self.tab_bars.get_mut(&key) {
+            tab_bar.configure(conn, dimensions, style).await?;
+        } else {
+            let tab_bar = TabBar::create(conn, dimensions, style).await?;
+            self.tab_bars.insert(key, tab_bar);
+        }
+
+        self.draw_tab_bar(conn, key).await
+    }
+
+    /// Redraws the tab bar of the pane at `key`.
+    async fn draw_tab_bar<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        key: XcrabKey,
+    ) -> Result<()> {
+        let Some(&tab_bar) = self.tab_bars.get(&key) else {
+            return Ok(());
+        };
+
+        let painter = match self.tab_painter {
+            Some(painter) => painter,
+            None => *self.tab_painter.insert(TabPainter::new(conn).await?),
+        };
+
+        let focused_key = self.focused_key();
+        let pane = self.tree[key].unwrap_pane();
+        let active = pane.active_child();
+
+        let tabs: Vec<Tab<'_>> = pane
+            .children
+            .iter()
+            .map(|&child| Tab {
+                title: self.tab_title(child),
+                focused: focused_key.is_some_and(|focused| self.tree.is_ancestor(child, focused)),
+                active: Some(child) == active,
+            })
+            .collect();
+
+        tab_bar.draw(conn, &painter, &tabs).await
+    }
+
+    /// Destroys the tab bars of panes that have been removed or are no longer tabbed or stacked.
+    async fn free_stale_tab_bars<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+    ) -> Result<()> {
+        let stale: Vec<XcrabKey> = self
+            .tab_bars
+            .keys()
+            .copied()
+            .filter(|&key| match self.tree.get(key).map(|rect| &rect.contents) {
+                Some(RectangleContents::Pane(pane))
+                    if pane.shown_mode() != PaneMode::Split && !pane.children.is_empty() =>
+                {
+                    // automatic layouts don't show tab bars at all
+                    self.workspaces[self.workspace_of_key(key)].layout != LayoutKind::Tree
+                }
+                _ => true,
+            })
+            .collect();
+
+        for key in stale {
+            self.tab_bars.remove(&key).unwrap().destroy(conn).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether `win` is the tab bar of some pane.
+    pub fn has_tab_bar(&self, win: Window) -> bool {
+        self.tab_bars.values().any(|tab_bar| tab_bar.window == win)
+    }
+
+    /// Handles a click on a tab bar by focusing the tab that was clicked.
+    pub async fn click_tab_bar<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        win: Window,
+        x: i16,
+        y: i16,
+    ) -> Result<()> {
+        let Some((&key, tab_bar)) = self
+            .tab_bars
+            .iter()
+            .find(|(_, tab_bar)| tab_bar.window == win)
+        else {
+            return Ok(());
+        };
+
+        let pane = self.tree[key].unwrap_pane();
+
+        let target = tab_bar
+            .tab_at(x, y, pane.children.len())
+            .and_then(|index| self.active_leaf(pane.children[index]));
+
+        if let Some(target) = target {
+            self.set_focus(conn, target).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Redraws a tab bar after it has been exposed.
+    pub async fn expose_tab_bar<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        win: Window,
+    ) -> Result<()> {
+        let key = self
+            .tab_bars
+            .iter()
+            .find(|(_, tab_bar)| tab_bar.window == win)
+            .map(|(&key, _)| key);
+
+        if let Some(key) = key {
+            self.draw_tab_bar(conn, key).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Handles a change to one of the properties of `win`.
+    pub async fn update_property<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        win: Window,
+        property: Atom,
+    ) -> Result<()> {
+        if self.docks.contains_key(&win)
+            && (property == self.atoms.net_wm_strut || property == self.atoms.net_wm_strut_partial)
+        {
+            return self.update_dock(conn, win).await;
+        }
+
+        if !self.has_client(win) {
+            return Ok(());
+        }
+
+        if let Some(change) = Change::of(&self.atoms, property) {
+            self.update_metadata(conn, win, change).await?;
+        }
+
+        if property == WM_NORMAL_HINTS {
+            self.update_size_hints(conn, win).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Re-reads the size hints of `win` and puts it where they say it should be now.
+    async fn update_size_hints<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        win: Window,
+    ) -> Result<()> {
+        let hints = get_size_hints(conn, win).await?;
+
+        let Some(client) = self.client_mut(win) else {
+            return Ok(());
+        };
+
+        if client.frame.hints == hints {
+            return Ok(());
+        }
+
+        client.frame.hints = hints;
+
+        self.reconfigure(conn, win).await
+    }
+
+    /// Puts `win` where it goes again after something about its frame changed.
+    async fn reconfigure<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        win: Window,
+    ) -> Result<()> {
+        let Some(client) = self.client_mut(win) else {
+            return Ok(());
+        };
+
+        // even if its tile stays the same, the client inside it might not
+        client.applied = None;
+
+        if let Some(&key) = self.clients.get(&win) {
+            let ws = self.workspace_of_key(key);
+
+            if self.is_shown(ws) {
+                self.update_rectangle(conn, key, None).await?;
+            }
+        } else if let Some(ws) = self
+            .workspaces
+            .iter()
+            .position(|ws| ws.floating.contains_key(&win))
+        {
+            if self.is_shown(ws) {
+                self.update_floating(conn, ws).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Re-reads the part of the metadata of `win` that `change` is about, and lets everything
+    /// that depends on it know if it changed.
+    async fn update_metadata<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        win: Window,
+        change: Change,
+    ) -> Result<()> {
+        let atoms = self.atoms;
+
+        let Some(client) = self.client_mut(win) else {
+            return Ok(());
+        };
+
+        if client.metadata.refresh(conn, &atoms, win, change).await? {
+            self.metadata_changed(conn, win, change).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Catches up with a change to the metadata of `win`. Anything that shows or acts on the
+    /// metadata of clients should hook in here.
+    async fn metadata_changed<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        win: Window,
+        change: Change,
+    ) -> Result<()> {
+        match change {
+            Change::Title => {
+                self.redraw_tab_bars_of(conn, win).await?;
+                self.reapply_rules(conn, win).await
+            }
+            Change::Hints => {
+                // the input hint decides how the client gets focused
+                if let Some(client) = self.client_mut(win) {
+                    client.frame.focus = FocusModel::new(
+                        client.metadata.hints.input,
+                        client.frame.protocols.take_focus,
+                    );
+                }
+
+                Ok(())
+            }
+            // nothing needs these to be up to date yet
+            Change::Class
+            | Change::Role
+            | Change::Pid
+            | Change::WindowType
+            | Change::TransientFor => Ok(()),
+        }
+    }
+
+    /// Carries out the rules that are checked again when the title of `win` changes. Only what
+    /// can be changed about a client we already manage is: floating and where it's split off
+    /// from are left for `toggle_floating` and moving it.
+    async fn reapply_rules<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        win: Window,
+    ) -> Result<()> {
+        let atoms = self.atoms;
+
+        let Some(client) = self.client_mut(win) else {
+            return Ok(());
+        };
+
+        let ty = client.metadata.window_type(&atoms);
+        let actions = Actions::of(CONFIG.rules(), &client.metadata, ty, true);
+
+        let send = actions.workspace.is_some() && !client.sent_to_workspace;
+        client.sent_to_workspace |= send;
+
+        if actions.border_size.is_some() || actions.border_color.is_some() {
+            let frame = &mut client.frame;
+            frame.border_size = actions.border_size.unwrap_or(frame.border_size);
+            frame.border_color = actions.border_color.unwrap_or(frame.border_color);
+
+            let border = frame.border_size.into();
+            may_not_exist(set_cardinals(conn, win, atoms.net_frame_extents, &[border; 4]).await)?;
+            self.reconfigure(conn, win).await?;
+        }
+
+        if let Some(name) = actions.workspace.as_ref().filter(|_| send) {
+            let target = self.workspace_index(name);
+
+            self.move_to_workspace(conn, win, target).await?;
+        }
+
+        if let Some(fullscreen) = actions.fullscreen {
+            self.set_fullscreen(conn, win, fullscreen).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Redraws any tab bars showing `win`.
+    async fn redraw_tab_bars_of<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        win: Window,
+    ) -> Result<()> {
+        // floating and scratchpad clients don't show up in any tab bars
+        let Some(&key) = self.clients.get(&win) else {
+            return Ok(());
+        };
+
+        let tab_bars: Vec<XcrabKey> = self
+            .tab_bars
+            .keys()
+            .copied()
+            .filter(|&pane_key| self.tree.is_ancestor(pane_key, key))
+            .collect();
+
+        for pane_key in tab_bars {
+            self.draw_tab_bar(conn, pane_key).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Switches the pane containing the focused client to the given mode, wrapping the client in
+    /// a new pane if it doesn't have one.
+    pub async fn set_pane_mode<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        mode: PaneMode,
+    ) -> Result<()> {
+        let Some(key) = self.focused_key() else {
+            return Ok(());
+        };
+
+        let current = self.current;
+        self.tree
+            .set_mode(key, mode, &mut self.workspaces[current].root);
+
+        self.update_rectangle(conn, self.tree.root_of(key), None)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Switches to the given layout and re-places every client.
+    pub async fn set_layout<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        layout: LayoutKind,
+    ) -> Result<()> {
+        self.workspace_mut().layout = layout;
+
+        self.update_all(conn).await?;
+
+        Ok(())
+    }
+
+    /// Moves the focused client out of the tree into the floating layer, or back into the tree
+    /// where it was before if it is already floating.
+    pub async fn toggle_floating<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+    ) -> Result<()> {
+        let Some(focused) = self.focused() else {
+            return Ok(());
+        };
+
+        let current = self.current;
+
+        if let Some(floating) = self.workspace_mut().floating.remove(&focused) {
+            let restored = floating
+                .slot
+                .and_then(|slot| self.insert_at_slot(current, floating.client.clone(), slot));
+
+            match restored {
+                Some(parent_key) => {
+                    self.focus_update_map(conn, current, floating.client.frame, parent_key)
+                        .await?;
+                }
+                None => {
+                    self.tile_client(conn, current, floating.client, Direction::Right)
+                        .await?;
+                }
+            }
+        } else if let Some(key) = self.focused_key() {
+            let dimensions = self.tree[key].cached_dimensions;
+
+            self.clients.remove(&focused);
+            let (client, slot) = self.take_from_tree(key);
+
+            self.add_floating(conn, current, client, dimensions, slot)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Puts `client` back into the tree of workspace `ws` at `slot`, returning the key of its new
+    /// parent. Returns `None` if neither the pane it was in nor its neighbour are still there.
+    fn insert_at_slot(&mut self, ws: usize, client: Client, slot: TreeSlot) -> Option<XcrabKey> {
+        let win = client.frame.win;
+
+        let key = self
+            .tree
+            .insert_at_slot(client, slot, &mut self.workspaces[ws].root)?;
+        self.clients.insert(win, key);
+
+        Some(self.tree[key].parent)
+    }
+
+    /// Removes the client at `key` from the tree (but not from `clients`), along with any panes
+    /// that are left empty. Returns the client and where in the tree it was.
+    fn take_from_tree(&mut self, key: XcrabKey) -> (Client, Option<TreeSlot>) {
+        let ws = self.workspace_of_key(key);
+        let (mut client, slot) = self.tree.take(key, &mut self.workspaces[ws].root);
+
+        // wherever it ends up next, its frame will have to be moved there
+        client.applied = None;
+
+        #[cfg(debug_assertions)]
+        self.check_tree();
+
+        (client, slot)
+    }
+
+    /// Tells the client `win` where it really is, after we didn't let it move or resize itself.
+    pub async fn deny_configure<Dpy: AsyncDisplay + ?Sized>(
+        &self,
+        conn: &mut Dpy,
+        win: Window,
+    ) -> Result<()> {
+        match self.client(win) {
+            Some(client) => client.frame.notify_geometry(conn).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Handles a `ConfigureRequest` from a floating client by moving and resizing its frame the
+    /// way it asked.
+    pub async fn configure_floating<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        ev: &ConfigureRequestEvent,
+    ) -> Result<()> {
+        let focused = self.focused().unwrap_or(Window::const_from_xid(0));
+
+        let Some(floating) = self.find_floating_mut(ev.window) else {
+            return Ok(());
+        };
+
+        let border = floating.client.frame.border_size.saturating_mul(2);
+        let dimensions = &mut floating.dimensions;
+
+        if ev.value_mask.x() {
+            dimensions.x = u16::try_from(ev.x).unwrap_or(0);
+        }
+        if ev.value_mask.y() {
+            dimensions.y = u16::try_from(ev.y).unwrap_or(0);
+        }
+        if ev.value_mask.width() {
+            dimensions.width = ev.width.saturating_add(border);
+        }
+        if ev.value_mask.height() {
+            dimensions.height = ev.height.saturating_add(border);
+        }
+
+        // the new geometry will be used once the client leaves fullscreen
+        if floating.client.fullscreen {
+            return Ok(());
+        }
+
+        floating
+            .client
+            .frame
+            .configure(conn, floating.dimensions, false, focused)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Toggles fullscreen for the focused client.
+    pub async fn toggle_fullscreen<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+    ) -> Result<()> {
+        let Some(focused) = self.focused() else {
+            return Ok(());
+        };
+
+        let fullscreen = self.client(focused).is_some_and(|client| client.fullscreen);
+
+        self.set_fullscreen(conn, focused, !fullscreen).await
+    }
+
+    /// Puts `win` into or takes it out of fullscreen, and tells it about it through
+    /// `_NET_WM_STATE`.
+    pub async fn set_fullscreen<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        win: Window,
+        fullscreen: bool,
+    ) -> Result<()> {
+        let Some(client) = self.client_mut(win) else {
+            return Ok(());
+        };
+
+        if client.fullscreen == fullscreen {
+            return Ok(());
+        }
+
+        client.fullscreen = fullscreen;
+
+        let state: &[Atom] = if fullscreen {
+            &[self.atoms.net_wm_state_fullscreen]
+        } else {
+            &[]
+        };
+        may_not_exist(set_atoms(conn, win, self.atoms.net_wm_state, state).await)?;
+
+        // leaving fullscreen puts the client back where the tree or floating layer says it goes.
+        // the client isn't necessarily on the current workspace, as it can ask for this itself
+        if let Some(ws) = self.workspace_of(win).filter(|&ws| self.is_shown(ws)) {
+            self.update_workspace(conn, ws).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Handles a message sent to us by a client.
+    pub async fn client_message<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        ev: &ClientMessageEvent,
+    ) -> Result<()> {
+        let data = ev.data.longs();
+
+        // answers to our pings are sent to the root window, with the client in the message
+        if ev.ty == self.atoms.wm_protocols && data[0] == self.atoms.net_wm_ping.xid {
+            return self.pong(conn, Window::const_from_xid(data[2])).await;
+        }
+
+        if !self.has_client(ev.window) {
+            return Ok(());
+        }
+
+        if ev.ty == self.atoms.net_active_window {
+            self.activate(conn, ev.window).await?;
+        } else if ev.ty == self.atoms.net_close_window {
+            self.close_client(conn, ev.window).await?;
+        } else if ev.ty == self.atoms.net_wm_desktop {
+            // `0xFFFFFFFF` asks for the client to be on every workspace, which we can't do
+            let target = usize::try_from(data[0]).unwrap_or(usize::MAX);
+
+            if target < self.workspaces.len() {
+                self.move_to_workspace(conn, ev.window, target).await?;
+            }
+        } else if ev.ty == self.atoms.net_wm_state {
+            // the message can change up to two states at once
+            if data[1..=2].contains(&self.atoms.net_wm_state_fullscreen.xid) {
+                let fullscreen = self
+                    .client(ev.window)
+                    .is_some_and(|client| client.fullscreen);
+
+                let fullscreen = match data[0] {
+                    NET_WM_STATE_REMOVE => false,
+                    NET_WM_STATE_ADD => true,
+                    NET_WM_STATE_TOGGLE => !fullscreen,
+                    _ => return Ok(()),
+                };
+
+                self.set_fullscreen(conn, ev.window, fullscreen).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The information we keep about `win`, wherever it is.
+    fn client(&self, win: Window) -> Option<&Client> {
+        if let Some(floating) = self.find_floating(win).or_else(|| self.find_stashed(win)) {
+            return Some(&floating.client);
+        }
+
+        match &self.tree.get(*self.clients.get(&win)?)?.contents {
+            RectangleContents::Client(client) => Some(client),
+            RectangleContents::Pane(_) => None,
+        }
+    }
+
+    fn client_mut(&mut self, win: Window) -> Option<&mut Client> {
+        if self.is_floating(win) {
+            return self
+                .find_floating_mut(win)
+                .map(|floating| &mut floating.client);
+        }
+
+        if let Some(floating) = self
+            .scratchpad
+            .iter_mut()
+            .find(|f| f.client.frame.win == win)
+        {
+            return Some(&mut floating.client);
+        }
+
+        match &mut self.tree.get_mut(*self.clients.get(&win)?)?.contents {
+            RectangleContents::Client(client) => Some(client),
+            RectangleContents::Pane(_) => None,
+        }
+    }
+
+    /// Shows the workspace called `name`, creating it if it doesn't exist.
+    pub async fn switch_workspace<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        name: &str,
+    ) -> Result<()> {
+        let index = self.workspace_index(name);
+
+        self.show_workspace(conn, index).await
+    }
+
+    /// Hides the current workspace and shows the one at `index` instead.
+    async fn show_workspace<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        index: usize,
+    ) -> Result<()> {
+        let previous = self.current;
+
+        if index == previous {
+            self.update_all(conn).await?;
+        } else if self.is_shown(index) {
+            // it's already on another output, so just go there
+            self.current = index;
+
+            // redraw the borders of the workspace we came from, since it lost focus
+            self.update_workspace(conn, previous).await?;
+            self.update_all(conn).await?;
+        } else {
+            let output = self.output_of(previous).unwrap();
+
+            self.set_workspace_mapped(conn, previous, false).await?;
+
+            self.outputs[output].workspace = index;
+            self.current = index;
+
+            // lay things out before mapping them so they don't jump around
+            self.update_all(conn).await?;
+
+            self.set_workspace_mapped(conn, index, true).await?;
+        }
+
+        self.update_focused(conn).await?;
+        self.update_desktops(conn).await
+    }
+
+    /// Maps or unmaps the frames and tab bars of everything on workspace `ws`.
+    ///
+    /// Only the frames are unmapped, so the clients themselves stay mapped and we don't get an
+    /// `UnmapNotify` for them that would look like they were withdrawn.
+    async fn set_workspace_mapped<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        ws: usize,
+        mapped: bool,
+    ) -> Result<()> {
+        let workspace = &self.workspaces[ws];
+
+        let mut frames: Vec<FramedWindow> = workspace
+            .floating
+            .values()
+            .map(|floating| floating.client.frame)
+            .collect();
+        let mut windows = Vec::new();
+
+        if let Some(root) = workspace.root {
+            for key in self.tree.subtree(root) {
+                match &self.tree[key].contents {
+                    RectangleContents::Client(client) => frames.push(client.frame),
+                    RectangleContents::Pane(_) => {
+                        if let Some(tab_bar) = self.tab_bars.get(&key) {
+                            windows.push(tab_bar.window);
+                        }
+                    }
+                }
+            }
+        }
+
+        let state = if mapped {
+            WmState::Normal
+        } else {
+            WmState::Iconic
+        };
+
+        for frame in frames {
+            frame.set_state(conn, &self.atoms, state).await?;
+            windows.push(frame.frame);
+        }
+
+        for window in windows {
+            if mapped {
+                window.map_async(conn).await?;
+            } else {
+                window.unmap_async(conn).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sends the focused client to the workspace called `name`, creating it if it doesn't exist.
+    pub async fn move_focused_to_workspace<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        name: &str,
+    ) -> Result<()> {
+        let target = self.workspace_index(name);
+
+        self.move_focused_to(conn, target).await
+    }
+
+    /// Sends the focused client to the workspace shown on the nearest output in the given
+    /// direction.
+    pub async fn move_focused_to_output<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        direction: Direction,
+    ) -> Result<()> {
+        if let Some(output) = self.find_output(direction) {
+            self.move_focused_to(conn, self.outputs[output].workspace)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Sends the focused client to workspace `target`. Focus stays on the current workspace.
+    async fn move_focused_to<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        target: usize,
+    ) -> Result<()> {
+        let Some(focused) = self.focused() else {
+            return Ok(());
+        };
+
+        self.move_to_workspace(conn, focused, target).await
+    }
+
+    /// Sends `win` to workspace `target`, where it becomes the focused client. Focus stays on
+    /// the current workspace, unless that is where `win` is going.
+    async fn move_to_workspace<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        win: Window,
+        target: usize,
+    ) -> Result<()> {
+        let Some(source) = self.workspace_of(win) else {
+            return Ok(());
+        };
+
+        if target == source {
+            return Ok(());
+        }
+
+        if let Some(frame) = self
+            .get_framed_window(win)
+            .filter(|_| !self.is_shown(target))
+        {
+            frame.frame.unmap_async(conn).await?;
+            frame.set_state(conn, &self.atoms, WmState::Iconic).await?;
+        }
+
+        if let Some(mut floating) = self.workspaces[source].floating.remove(&win) {
+            // the slot is in the old workspace's tree, so it's no use anymore
+            floating.slot = None;
+
+            if self.output_of(target) != self.output_of(source) {
+                let Dimensions { width, height, .. } = floating.dimensions;
+                floating.dimensions = self.usable_area(target).center(width, height);
+            }
+
+            if self.workspaces[source].focused == Some(win) {
+                self.refocus(source);
+            }
+
+            self.insert_floating(conn, target, floating).await?;
+        } else if let Some(key) = self.clients.remove(&win) {
+            let (client, _) = self.take_from_tree(key);
+
+            if self.workspaces[source].focused == Some(win) {
+                self.refocus(source);
+            }
+
+            self.tile_client(conn, target, client, Direction::Right)
+                .await?;
+        }
+
+        // the source might be on another output
+        if source != self.current && self.is_shown(source) {
+            self.update_workspace(conn, source).await?;
+        }
+
+        self.update_focused(conn).await?;
+        self.update_all(conn).await?;
+
+        // the workspace may have only just been created
+        self.update_desktops(conn).await?;
+        self.set_desktop(conn, win, target).await?;
+        self.update_client_list(conn).await
+    }
+
+    /// Hides the focused client in the scratchpad.
+    pub async fn move_focused_to_scratchpad<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+    ) -> Result<()> {
+        let Some(focused) = self.focused() else {
+            return Ok(());
+        };
+
+        let floating = if let Some(floating) = self.workspace_mut().floating.remove(&focused) {
+            floating
+        } else if let Some(key) = self.focused_key() {
+            let dimensions = self.tree[key].cached_dimensions;
+
+            self.clients.remove(&focused);
+            let (client, _) = self.take_from_tree(key);
+
+            Floating {
+                client,
+                dimensions,
+                slot: None,
+                scratchpad: true,
+            }
+        } else {
+            return Ok(());
+        };
+
+        self.stash(conn, self.current, floating).await?;
+        self.update_client_list(conn).await
+    }
+
+    /// Shows the oldest client in the scratchpad (or the oldest one whose `WM_CLASS` matches
+    /// `class`) floating in the middle of the focused output. If a matching client is already
+    /// out on the current workspace it is put away again instead.
+    pub async fn toggle_scratchpad<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        class: Option<&str>,
+    ) -> Result<()> {
+        let matches = |floating: &Floating| {
+            floating.scratchpad && class.map_or(true, |class| floating.client.has_class(class))
+        };
+
+        let focused = self.focused();
+        let shown = self
+            .workspace()
+            .floating
+            .iter()
+            .filter(|&(_, floating)| matches(floating))
+            // put away the focused one if there are a few out
+            .min_by_key(|&(&win, _)| (Some(win) != focused, win.xid))
+            .map(|(&win, _)| win);
+
+        if let Some(win) = shown {
+            self.unstash(conn, win).await?;
+            return self.update_client_list(conn).await;
+        }
+
+        // one that is out on another workspace gets brought over
+        let elsewhere = self
+            .workspaces
+            .iter()
+            .flat_map(|ws| ws.floating.iter())
+            .find(|&(_, floating)| matches(floating))
+            .map(|(&win, _)| win);
+
+        let index =
+            if let Some(win) = elsewhere {
+                self.unstash(conn, win).await?;
+                self.scratchpad.len() - 1
+            } else {
+                let Some(index) = self.scratchpad.iter().position(|floating| {
+                    class.map_or(true, |class| floating.client.has_class(class))
+                }) else {
+                    return Ok(());
+                };
+
+                index
+            };
+
+        self.show_stashed(conn, index).await
+    }
+
+    /// Takes the client at `index` out of the scratchpad and shows it in the middle of the
+    /// current workspace.
+    async fn show_stashed<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        index: usize,
+    ) -> Result<()> {
+        let mut floating = self.scratchpad.remove(index);
+
+        let Dimensions { width, height, .. } = floating.dimensions;
+        floating.dimensions = self.usable_area(self.current).center(width, height);
+
+        let win = floating.client.frame.win;
+
+        self.insert_floating(conn, self.current, floating).await?;
+        self.set_desktop(conn, win, self.current).await?;
+        self.update_client_list(conn).await
+    }
+
+    /// Puts the scratchpad client `win` back into the scratchpad.
+    async fn unstash<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        win: Window,
+    ) -> Result<()> {
+        let ws = self
+            .workspace_of(win)
+            .ok_or(XcrabError::ClientDoesntExist)?;
+        let floating = self.workspaces[ws]
+            .floating
+            .remove(&win)
+            .ok_or(XcrabError::ClientDoesntExist)?;
+
+        self.stash(conn, ws, floating).await
+    }
+
+    /// Hides `floating`, which was just taken off of workspace `ws`, in the scratchpad.
+    async fn stash<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        ws: usize,
+        floating: Floating,
+    ) -> Result<()> {
+        let win = floating.client.frame.win;
+
+        floating.client.frame.frame.unmap_async(conn).await?;
+        floating
+            .client
+            .frame
+            .set_state(conn, &self.atoms, WmState::Iconic)
+            .await?;
+
+        self.scratchpad.push(Floating {
+            slot: None,
+            scratchpad: true,
+            ..floating
+        });
+
+        if self.workspaces[ws].focused == Some(win) {
+            self.refocus(ws);
+        }
+
+        if ws == self.current {
+            self.update_focused(conn).await?;
+        }
+
+        if self.is_shown(ws) {
+            self.update_workspace(conn, ws).await?;
+        }
+
+        Ok(())
+    }
+
+    fn find_stashed(&self, win: Window) -> Option<&Floating> {
+        self.scratchpad
+            .iter()
+            .find(|floating| floating.client.frame.win == win)
+    }
+
+    /// Focuses the workspace on the nearest output in the given direction.
+    pub async fn focus_output<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        direction: Direction,
+    ) -> Result<()> {
+        if let Some(output) = self.find_output(direction) {
+            self.show_workspace(conn, self.outputs[output].workspace)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Finds the output nearest to the focused one in the given direction.
+    fn find_output(&self, direction: Direction) -> Option<usize> {
+        let current = self.outputs[self.output_of(self.current)?].dimensions;
+
+        self.outputs
+            .iter()
+            .enumerate()
+            .filter_map(|(i, output)| Some((i, current.distance_to(output.dimensions, direction)?)))
+            // prefer outputs that line up with this one, then whichever is closest
+            .min_by_key(|&(i, (primary, secondary))| (secondary > 0, primary, secondary, i))
+            .map(|(i, _)| i)
+    }
+
+    /// Whether `win` is one of our clients, tiled, floating or in the scratchpad.
+    pub fn has_client(&self, win: Window) -> bool {
+        self.clients.contains_key(&win) || self.is_floating(win) || self.find_stashed(win).is_some()
+    }
+
+    pub fn is_floating(&self, win: Window) -> bool {
+        self.find_floating(win).is_some()
+    }
+
+    /// The key of the focused client, if it is tiled.
+    fn focused_key(&self) -> Option<XcrabKey> {
+        self.focused()
+            .and_then(|win| self.clients.get(&win))
+            .copied()
+    }
+
+    pub async fn remove_client<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        win: Window,
+    ) -> Result<()> {
+        self.closing.remove(&win);
+        self.ignored_unmaps.remove(&win);
+
+        if let Some(index) = self
+            .scratchpad
+            .iter()
+            .position(|floating| floating.client.frame.win == win)
+        {
+            self.client_order.retain(|&client| client != win);
+            self.stacking.retain(|&client| client != win);
+
+            // nothing on screen to update
+            self.scratchpad
+                .remove(index)
+                .client
+                .frame
+                .unframe(conn, &self.atoms)
+                .await?;
+
+            return self.update_client_list(conn).await;
+        }
+
+        let ws = self
+            .workspace_of(win)
+            .ok_or(XcrabError::ClientDoesntExist)?;
+
+        let client = if let Some(floating) = self.workspaces[ws].floating.remove(&win) {
+            floating.client
+        } else {
+            let client_key = self
+                .clients
+                .remove(&win)
+                .ok_or(XcrabError::ClientDoesntExist)?;
+
+            self.take_from_tree(client_key).0
+        };
+
+        client.frame.unframe(conn, &self.atoms).await?;
+        self.client_order.retain(|&client| client != win);
+        self.stacking.retain(|&client| client != win);
+
+        if self.workspaces[ws].focused == Some(win) {
+            self.refocus(ws);
+        }
+
+        if ws == self.current {
+            self.update_focused(conn).await?;
+        }
+
+        if self.is_shown(ws) {
+            self.update_workspace(conn, ws).await?;
+        }
+
+        self.update_client_list(conn).await
+    }
+
+    /// Makes the next `UnmapNotify` for the client `win` be ignored, for when we unmap it
+    /// ourselves.
+    fn ignore_unmap(&mut self, win: Window) {
+        *self.ignored_unmaps.entry(win).or_default() += 1;
+    }
+
+    /// Handles the client `win` being unmapped, which means it withdrew unless it was us that
+    /// unmapped it. A `synthetic` unmap, sent by the client itself, is always a withdrawal: the
+    /// ICCCM has clients send one when they withdraw while they're already unmapped.
+    pub async fn unmap_notify<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        win: Window,
+        synthetic: bool,
+    ) -> Result<()> {
+        if !synthetic {
+            if let Some(count) = self.ignored_unmaps.get_mut(&win) {
+                *count -= 1;
+
+                if *count == 0 {
+                    self.ignored_unmaps.remove(&win);
+                }
+
+                return Ok(());
+            }
+        }
+
+        self.remove_client(conn, win).await
+    }
+
+    /// Picks a new client to focus on workspace `ws` after the focused one went away: whatever
+    /// had focus in the tree before, or some floating client if the tree is empty.
+    fn refocus(&mut self, ws: usize) {
+        let workspace = &self.workspaces[ws];
+
+        let focused = workspace
+            .root
+            .and_then(|root| self.active_leaf(root))
+            .or_else(|| workspace.floating.keys().copied().next());
+
+        self.workspaces[ws].focused = focused;
+    }
+
+    pub async fn destroy_focused_client<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+    ) -> Result<()> {
+        if let Some(focused) = self.focused() {
+            self.close_client(conn, focused).await
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Asks `win` to close, or kills it if it doesn't know how to or was already asked. We keep
+    /// managing it until it's actually gone.
+    pub async fn close_client<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        win: Window,
+    ) -> Result<()> {
+        let Some(frame) = self.get_framed_window(win) else {
+            return Ok(());
+        };
+
+        if self.closing.contains_key(&win) || !frame.protocols.delete_window {
+            return self.kill_client(conn, win).await;
+        }
+
+        let atoms = self.atoms;
+
+        frame
+            .send_protocol(conn, &atoms, atoms.wm_delete_window, self.time)
+            .await?;
+
+        // clients that answer pings get half the time to do so, and the other half to answer
+        // again once they're marked as hung
+        let timeout = if frame.protocols.ping {
+            frame
+                .send_protocol(conn, &atoms, atoms.net_wm_ping, self.time)
+                .await?;
+
+            CONFIG.close_timeout() / 2
+        } else {
+            CONFIG.close_timeout()
+        };
+
+        self.closing.insert(win, Some(Instant::now() + timeout));
+
+        Ok(())
+    }
+
+    /// When we next have to check on a client we asked to close.
+    pub fn close_deadline(&self) -> Option<Instant> {
+        self.closing.values().flatten().min().copied()
+    }
+
+    /// Checks on the closing clients that are still around when they should have closed or
+    /// answered our ping. Clients that can be pinged are marked as hung and pinged again the first
+    /// time, and killed the second time. Everything else is killed straight away.
+    pub async fn check_closing<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+    ) -> Result<()> {
+        let now = Instant::now();
+        let overdue: Vec<Window> = self
+            .closing
+            .iter()
+            .filter(|&(_, deadline)| deadline.is_some_and(|deadline| deadline <= now))
+            .map(|(&win, _)| win)
+            .collect();
+
+        for win in overdue {
+            let Some(frame) = self.client(win).map(|client| client.frame) else {
+                self.closing.remove(&win);
+                continue;
+            };
+
+            if frame.hung || !frame.protocols.ping {
+                self.kill_client(conn, win).await?;
+                continue;
+            }
+
+            self.set_hung(conn, win, true).await?;
+
+            frame
+                .send_protocol(conn, &self.atoms, self.atoms.net_wm_ping, self.time)
+                .await?;
+            self.closing
+                .insert(win, Some(now + CONFIG.close_timeout() / 2));
+        }
+
+        Ok(())
+    }
 
-        self.focus_update_map(conn, frame, parent_key).await?;
+    /// Handles a client answering our ping, which means it isn't hung and can take as long as it
+    /// likes to close.
+    async fn pong<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        win: Window,
+    ) -> Result<()> {
+        if let Some(deadline) = self.closing.get_mut(&win) {
+            *deadline = None;
+        }
 
-        Ok(())
+        self.set_hung(conn, win, false).await
     }
 
-    /// Adds a new client in the given direction directly adjacent to the focused window, creating a new pane if needed.
-    pub async fn add_client_direction_immediate<Dpy: AsyncDisplay + ?Sized>(
+    /// Marks `win` as hung or not, which shows in its border.
+    async fn set_hung<Dpy: AsyncDisplay + ?Sized>(
         &mut self,
         conn: &mut Dpy,
         win: Window,
-        direction: Direction,
+        hung: bool,
     ) -> Result<()> {
-        #[allow(clippy::enum_glob_use)]
-        use {Direction::*, Directionality::*};
+        let focused = self.focused().unwrap_or(Window::const_from_xid(0));
 
-        let focused = match self.focused {
-            Some(v) => v,
-            None => return self.add_first_client(conn, win).await,
+        let Some(client) = self.client_mut(win) else {
+            return Ok(());
         };
 
-        // frame the window
-        let frame = frame(conn, win).await?;
+        if client.frame.hung == hung {
+            return Ok(());
+        }
 
-        // get the focused client
-        let focused_client_key = *self.clients.get(&focused).unwrap();
-        let focused_client = self.rects.get(focused_client_key).unwrap();
+        client.frame.hung = hung;
+        client.frame.set_border(conn, focused).await
+    }
 
-        // get the parent of the focused client
-        let mut parent_key = focused_client.parent;
-        let parent_pane_dir = match &self.rects.get(parent_key).unwrap().contents {
-            RectangleContents::Pane(pane) => Some(pane.directionality),
-            RectangleContents::Client(_) => None,
-        };
+    /// Kills the client owning `win`: with `SIGKILL` if it runs on this machine and told us its
+    /// pid, and with `KillClient` otherwise. Its windows going away takes care of the rest.
+    async fn kill_client<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        win: Window,
+    ) -> Result<()> {
+        self.closing.remove(&win);
+
+        // a client that's already gone has no machine, so only its connection is left to kill
+        let local = get_client_machine(conn, win)
+            .await?
+            .is_some_and(|machine| Some(machine) == hostname());
+
+        if local {
+            let pid = self
+                .client(win)
+                .and_then(|client| client.metadata.pid)
+                .and_then(|pid| libc::pid_t::try_from(pid).ok())
+                .filter(|&pid| pid > 0 && pid.unsigned_abs() != std::process::id());
+
+            // SAFETY: `kill` doesn't touch our memory, and the worst it can do to us is fail
+            if pid.is_some_and(|pid| unsafe { libc::kill(pid, libc::SIGKILL) } == 0) {
+                return Ok(());
+            }
+        }
 
-        // find the target directionality
-        let target_directionality = match direction {
-            Up | Down => Vertical,
-            Left | Right => Horizontal,
-        };
+        may_not_exist(
+            conn.exchange_request_async(KillClientRequest {
+                req_type: 113, // constant, specified in x protocol docs.
+                length: 2,     // constant, specified in x protocol docs.
+                resource: win.xid,
+            })
+            .await,
+        )?;
+
+        Ok(())
+    }
 
-        // if the parent's directionality is wrong...
-        // note: the `None` case is hit if we are the root client
-        if parent_pane_dir.is_none() || parent_pane_dir.unwrap() != target_directionality {
-            // insert a pane above the client with the right directionality
-            parent_key = self
-                .insert_pane_above(focused_client_key, target_directionality)
-                .unwrap();
+    /// Focuses `win` wherever it is, switching to its workspace or taking it out of the
+    /// scratchpad if need be.
+    pub async fn activate<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        win: Window,
+    ) -> Result<()> {
+        if let Some(index) = self
+            .scratchpad
+            .iter()
+            .position(|floating| floating.client.frame.win == win)
+        {
+            return self.show_stashed(conn, index).await;
         }
 
-        // create the rect
-        let new_rect_key = self.rects.insert(Rectangle {
-            parent: parent_key,
-            // this default will be overriden by the `update_rectangle` down below
-            cached_dimensions: Dimensions::default(),
-            contents: RectangleContents::Client(Client { frame }),
-        });
+        self.set_focus(conn, win).await
+    }
 
-        // get the parent of the focused client (may have been modified above)
-        let parent_pane = self.rects.get_mut(parent_key).unwrap().unwrap_pane_mut();
+    pub async fn set_focus<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        win: Window,
+    ) -> Result<()> {
+        let ws = self
+            .workspace_of(win)
+            .ok_or(XcrabError::ClientDoesntExist)?;
 
-        // get the index we want to insert at
-        let mut index = parent_pane
-            .children
-            .iter()
-            .copied()
-            .position(|v| v == focused_client_key)
-            .unwrap();
+        self.workspaces[ws].focused = Some(win);
 
-        if let Down | Right = direction {
-            index += 1;
+        if let Some(&client_key) = self.clients.get(&win) {
+            self.tree.mark_active(client_key);
         }
 
-        // insert
-        parent_pane.children.insert(index, new_rect_key);
+        if ws != self.current {
+            // this takes care of telling x about the focus and laying everything out
+            return self.show_workspace(conn, ws).await;
+        }
 
-        self.clients.insert(win, new_rect_key);
+        self.update_focused(conn).await?;
 
-        self.focus_update_map(conn, frame, parent_key).await?;
+        // the previously focused client may live in a different pane, so redraw everything to
+        // make sure its border gets reset as well
+        self.update_all(conn).await?;
 
         Ok(())
     }
 
-    async fn add_first_client<Dpy: AsyncDisplay + ?Sized>(
+    /// Moves focus to the next tab in the given direction, if the focused client is in a tabbed
+    /// or stacked pane, and otherwise to the nearest client in that direction. The automatic
+    /// layouts fall back to the next or previous client in the order they place them.
+    pub async fn focus_direction<Dpy: AsyncDisplay + ?Sized>(
         &mut self,
         conn: &mut Dpy,
-        win: Window,
+        direction: Direction,
     ) -> Result<()> {
-        let frame = frame(conn, win).await?;
-
-        let root_geo = conn.default_root().geometry_immediate_async(conn).await?;
-
-        let outer_gap_size = CONFIG.outer_gap_size();
-        let key = self.rects.insert_with_key(|key| Rectangle {
-            parent: key,
-            cached_dimensions: Dimensions {
-                x: u16::try_from(root_geo.x).unwrap() + outer_gap_size,
-                y: u16::try_from(root_geo.y).unwrap() + outer_gap_size,
-                width: root_geo.width - 2 * outer_gap_size,
-                height: root_geo.height - 2 * outer_gap_size,
-            },
-            contents: RectangleContents::Client(Client { frame }),
+        let neighbour = self.focused().and_then(|focused| {
+            self.tab_beside(focused, direction)
+                .or_else(|| self.find_neighbour(focused, direction))
+                .or_else(|| self.cycle_neighbour(focused, direction))
         });
 
-        self.clients.insert(win, key);
-
-        self.focus_update_map(conn, frame, key).await?;
+        if let Some(neighbour) = neighbour {
+            self.set_focus(conn, neighbour).await?;
+        } else {
+            // nothing more in that direction on this output, so go to the next one
+            self.focus_output(conn, direction).await?;
+        }
 
         Ok(())
     }
 
-    // TODO: maybe `https://crates.io/crates/async_recursion`?
-    #[must_use]
-    fn update_rectangle<'a, Dpy: AsyncDisplay + ?Sized>(
-        &'a mut self,
-        conn: &'a mut Dpy,
-        key: XcrabKey,
-        dimensions: Option<Dimensions>,
-    ) -> Pin<Box<dyn Future<Output = Result<()>> + 'a>> {
-        Box::pin(async move {
-            let rect = self
-                .rects
-                .get_mut(key)
-                .ok_or(XcrabError::ClientDoesntExist)?;
+    /// The client in the tab next to `win`'s in the given direction, see `Tree::tab_beside`.
+    fn tab_beside(&self, win: Window, direction: Direction) -> Option<Window> {
+        let key = *self.clients.get(&win)?;
 
-            let dimensions = dimensions.unwrap_or(rect.cached_dimensions);
-            rect.cached_dimensions = dimensions;
-
-            match &mut rect.contents {
-                RectangleContents::Pane(pane) => {
-                    if !pane.children.is_empty() {
-                        let new_dimensions =
-                            dimensions.split(pane.directionality, pane.children.len());
-
-                        for (key, dimensions) in pane
-                            .children
-                            .clone()
-                            .into_iter()
-                            .zip(new_dimensions.into_iter())
-                        {
-                            self.update_rectangle(conn, key, Some(dimensions)).await?;
-                        }
-                    }
-                }
-                RectangleContents::Client(client) => {
-                    client
-                        .frame
-                        .configure(
-                            conn,
-                            ConfigureWindowParameters {
-                                x: Some(dimensions.x.into()),
-                                y: Some(dimensions.y.into()),
-                                width: Some(dimensions.width.into()),
-                                height: Some(dimensions.height.into()),
-                                ..Default::default()
-                            },
-                            self.focused.unwrap(),
-                        )
-                        .await?;
-                }
-            }
+        if self.workspaces[self.workspace_of_key(key)].layout != LayoutKind::Tree {
+            return None;
+        }
 
-            Ok(())
-        })
+        self.active_leaf(self.tree.tab_beside(key, direction)?)
     }
 
-    pub fn has_client(&self, win: Window) -> bool {
-        self.clients.contains_key(&win)
-    }
+    /// Finds the client nearest to `win` in the given direction. This goes by where the clients
+    /// are on screen rather than by their position in the tree, so that e.g. going left from the
+    /// top of a vertical pane doesn't land on its bottom.
+    fn find_neighbour(&self, win: Window, direction: Direction) -> Option<Window> {
+        let dimensions = self.tree.get(*self.clients.get(&win)?)?.cached_dimensions;
 
-    pub async fn remove_client<Dpy: AsyncDisplay + ?Sized>(
-        &mut self,
-        conn: &mut Dpy,
-        win: Window,
-    ) -> Result<()> {
-        let client_key = *self
+        let candidates = self
             .clients
-            .get(&win)
-            .ok_or(XcrabError::ClientDoesntExist)?;
-
-        let client = self.rects.get(client_key).unwrap();
+            .iter()
+            .filter(|&(&other, &key)| {
+                other != win
+                    && Some(self.tree.root_of(key)) == self.tree_root()
+                    && self.is_visible(key)
+            })
+            .map(|(&other, &key)| (other.xid, self.tree[key].cached_dimensions));
 
-        client.unwrap_client().frame.unframe(conn).await?;
+        dimensions
+            .nearest(candidates, direction)
+            .map(Window::const_from_xid)
+    }
 
-        let parent_key = client.parent;
-        let parent = self.rects.get_mut(parent_key).unwrap();
+    /// The client after `win` in the order the automatic layouts place them, going right or
+    /// down, or the one before it going left or up. This is how to get to the clients monocle
+    /// covers up, which are never anywhere on screen. Returns `None` in the tree layout, and at
+    /// either end of the order.
+    fn cycle_neighbour(&self, win: Window, direction: Direction) -> Option<Window> {
+        let key = *self.clients.get(&win)?;
 
-        parent
-            .unwrap_pane_mut()
-            .children
-            .retain(|&v| v != client_key);
+        if self.workspaces[self.workspace_of_key(key)].layout == LayoutKind::Tree {
+            return None;
+        }
 
-        self.clients.remove(&win);
-        self.rects.remove(client_key);
+        let leaves = self.tree.leaves(self.tree.root_of(key));
+        let index = leaves.iter().position(|&leaf| leaf == key)?;
+        let index = if direction.is_forward() {
+            index.checked_add(1)?
+        } else {
+            index.checked_sub(1)?
+        };
 
-        if self.focused.unwrap() == win {
-            self.focused = self.clients.keys().copied().next();
+        self.active_leaf(*leaves.get(index)?)
+    }
 
-            self.update_focused(conn).await?;
-        }
+    /// Moves the focused client one step in the given direction, see `Tree::move_rect`.
+    pub async fn move_focused<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        direction: Direction,
+    ) -> Result<()> {
+        let Some(key) = self.focused_key() else {
+            return Ok(());
+        };
 
-        self.update_rectangle(conn, parent_key, None).await?;
+        let current = self.current;
 
-        // TODO: remove panes if they have 1 or 0 children
+        if self
+            .tree
+            .move_rect(key, direction, &mut self.workspaces[current].root)
+        {
+            self.update_rectangle(conn, self.tree.root_of(key), None)
+                .await?;
+        }
 
         Ok(())
     }
 
-    pub async fn destroy_focused_client<Dpy: AsyncDisplay + ?Sized>(
+    /// Swaps the focused client with its nearest neighbour in the given direction.
+    pub async fn swap_focused<Dpy: AsyncDisplay + ?Sized>(
         &mut self,
         conn: &mut Dpy,
+        direction: Direction,
     ) -> Result<()> {
-        if let Some(focused) = self.focused {
-            let client_key = *self
-                .clients
-                .get(&focused)
-                .ok_or(XcrabError::ClientDoesntExist)?;
+        let Some(focused) = self.focused() else {
+            return Ok(());
+        };
+
+        let Some(neighbour) = self.find_neighbour(focused, direction) else {
+            return Ok(());
+        };
 
-            let frame = self.rects.get(client_key).unwrap().unwrap_client().frame;
+        let key = self.clients[&focused];
+        let neighbour_key = self.clients[&neighbour];
 
-            self.remove_client(conn, focused).await?;
+        self.tree.swap(key, neighbour_key);
 
-            frame.kill_client(conn).await?;
+        self.update_rectangle(conn, self.tree.root_of(key), None)
+            .await?;
 
-            Ok(())
-        } else {
-            Ok(())
-        }
+        Ok(())
     }
 
-    pub async fn set_focus<Dpy: AsyncDisplay + ?Sized>(
+    /// Grows the focused client by moving its edge in the given direction, see `Tree::resize`.
+    pub async fn resize_focused<Dpy: AsyncDisplay + ?Sized>(
         &mut self,
         conn: &mut Dpy,
-        win: Window,
+        direction: Direction,
+        amount: ResizeAmount,
     ) -> Result<()> {
-        let client_key = *self
-            .clients
-            .get(&win)
-            .ok_or(XcrabError::ClientDoesntExist)?;
+        let Some(key) = self.focused_key() else {
+            return Ok(());
+        };
 
-        self.focused = Some(win);
+        if let Some(pane_key) = self.tree.resize(key, direction, amount, CONFIG.gap_size()) {
+            self.update_rectangle(conn, pane_key, None).await?;
+        }
 
-        self.update_focused(conn).await?;
+        Ok(())
+    }
 
-        self.update_rectangle(conn, self.rects.get(client_key).unwrap().parent, None)
-            .await?;
+    /// Resets the sizes of every pane in the tree back to even splits.
+    pub async fn balance<Dpy: AsyncDisplay + ?Sized>(&mut self, conn: &mut Dpy) -> Result<()> {
+        let Some(root_key) = self.tree_root() else {
+            return Ok(());
+        };
+
+        self.tree.balance(root_key);
+
+        self.update_rectangle(conn, root_key, None).await?;
 
         Ok(())
     }
 
-    pub async fn get_focused(&self) -> Option<Window> {
-        self.focused
+    /// Panics if the tree is in a state that `normalize` should have fixed, or if the tree,
+    /// `clients` and the workspaces disagree about who is where.
+    #[cfg(debug_assertions)]
+    fn check_tree(&self) {
+        let roots: Vec<XcrabKey> = self.workspaces.iter().filter_map(|ws| ws.root).collect();
+
+        self.tree.check(roots.iter().copied());
+
+        for key in roots.into_iter().flat_map(|root| self.tree.leaves(root)) {
+            let win = self.tree[key].unwrap_client().frame.win;
+
+            assert_eq!(
+                self.clients.get(&win),
+                Some(&key),
+                "client {key:?} missing from `clients`"
+            );
+        }
+
+        for (&win, &key) in &self.clients {
+            assert!(
+                matches!(self.tree.get(key).map(|rect| &rect.contents), Some(RectangleContents::Client(client)) if client.frame.win == win),
+                "`clients` entry for {win:?} points at the wrong rect"
+            );
+        }
+    }
+
+    pub fn get_focused(&self) -> Option<Window> {
+        self.focused()
     }
 
-    pub async fn get_framed_window(&self, window: Window) -> FramedWindow {
-        let focused_key = self.clients.get(&window).unwrap();
-        let focused = self.rects.get(*focused_key).unwrap();
-        let focused_frame = focused.unwrap_client().frame;
-        focused_frame
+    /// The frame of `window`, wherever it is, or `None` if we don't manage it.
+    pub fn get_framed_window(&self, window: Window) -> Option<FramedWindow> {
+        self.client(window).map(|client| client.frame)
     }
 }
 
@@ -638,59 +2782,96 @@ pub fn may_not_exist(res: breadx::Result) -> breadx::Result {
 pub struct FramedWindow {
     pub frame: Window,
     pub win: Window,
+    pub hints: SizeHints,
+    pub focus: FocusModel,
+    pub protocols: Protocols,
+    /// Whether the client was asked to close and stopped answering our pings.
+    pub hung: bool,
+    /// The width of the border, and its colour while the client isn't focused. These come from
+    /// the config, unless a rule says otherwise.
+    pub border_size: u16,
+    pub border_color: u32,
 }
 
 impl FramedWindow {
+    /// Puts the frame, borders and all, in `dimensions` and sizes the client the way its size
+    /// hints ask. A `tiled` client never gets bigger than `dimensions`, and is centered in it if
+    /// it can't fill it, while floating ones get exactly what their hints say.
     async fn configure<Dpy: AsyncDisplay + ?Sized>(
         self,
         conn: &mut Dpy,
-        props: ConfigureWindowParameters,
+        dimensions: Dimensions,
+        tiled: bool,
         focused_win: Window,
     ) -> Result<()> {
-        let inset = 2 * u32::from(CONFIG.border_size());
+        let inset = self.border_size.saturating_mul(2);
 
-        let width = props.width.map(|v| v - inset);
-        let height = props.height.map(|v| v - inset);
+        // x doesn't allow empty windows, so anything too small to fit inside its borders gets
+        // a single pixel
+        let available_width = dimensions.width.saturating_sub(inset).max(1);
+        let available_height = dimensions.height.saturating_sub(inset).max(1);
 
-        let focused = focused_win == self.win;
+        let (mut width, mut height) = self.hints.constrain(available_width, available_height);
 
-        self.frame
-            .change_attributes_async(
-                conn,
-                WindowParameters {
-                    border_pixel: Some(if focused {
-                        CONFIG.focused_color()
-                    } else {
-                        CONFIG.border_color()
-                    }),
-                    ..Default::default()
-                },
-            )
-            .await?;
+        if tiled {
+            width = width.min(available_width);
+            height = height.min(available_height);
+        }
+
+        let x = dimensions
+            .x
+            .saturating_add(available_width.saturating_sub(width) / 2);
+        let y = dimensions
+            .y
+            .saturating_add(available_height.saturating_sub(height) / 2);
+
+        self.set_border(conn, focused_win).await?;
 
         self.frame
             .configure_async(
                 conn,
                 ConfigureWindowParameters {
-                    x: props.x,
-                    y: props.y,
-                    width,
-                    height,
-                    border_width: Some(CONFIG.border_size().into()),
+                    x: Some(x.into()),
+                    y: Some(y.into()),
+                    width: Some(width.into()),
+                    height: Some(height.into()),
+                    border_width: Some(self.border_size.into()),
                     ..Default::default()
                 },
             )
             .await?;
 
+        self.fill_frame(conn, width, height).await?;
+
+        let border = self.border_size;
+        self.notify_configure(
+            conn,
+            x.saturating_add(border),
+            y.saturating_add(border),
+            width,
+            height,
+        )
+        .await
+    }
+
+    /// Makes the client cover its frame, which is `width` by `height` on the inside. Its own
+    /// border is taken away, so that where it is is exactly where we say it is.
+    async fn fill_frame<Dpy: AsyncDisplay + ?Sized>(
+        self,
+        conn: &mut Dpy,
+        width: u16,
+        height: u16,
+    ) -> Result<()> {
         may_not_exist(
             self.win
                 .configure_async(
                     conn,
                     ConfigureWindowParameters {
-                        x: Some(-1),
-                        y: Some(-1),
-                        width,
-                        height,
+                        x: Some(0),
+                        y: Some(0),
+                        width: Some(width.into()),
+                        height: Some(height.into()),
+                        border_width: Some(0),
                         ..Default::default()
                     },
                 )
@@ -700,116 +2881,195 @@ impl FramedWindow {
         Ok(())
     }
 
-    async fn map<Dpy: AsyncDisplay + ?Sized>(self, conn: &mut Dpy) -> Result<()> {
-        may_not_exist(self.win.map_async(conn).await)?;
-        self.frame.map_async(conn).await?;
+    /// Sends the synthetic `ConfigureNotify` the ICCCM asks for, telling the client it is at
+    /// `x`, `y` on the root window. Clients inside a frame don't get a real one when the frame
+    /// moves, and some of them wait for one before drawing.
+    async fn notify_configure<Dpy: AsyncDisplay + ?Sized>(
+        self,
+        conn: &mut Dpy,
+        x: u16,
+        y: u16,
+        width: u16,
+        height: u16,
+    ) -> Result<()> {
+        let event = Event::ConfigureNotify(ConfigureNotifyEvent {
+            event_type: 22, // constant, check x protocol docs
+            sequence: 0,
+            event: self.win,
+            window: self.win,
+            above_sibling: Window::const_from_xid(0),
+            x: i16::try_from(x).unwrap_or(i16::MAX),
+            y: i16::try_from(y).unwrap_or(i16::MAX),
+            width,
+            height,
+            border_width: 0,
+            override_redirect: false,
+        });
+
+        may_not_exist(
+            conn.send_event_async(self.win, EventMask::STRUCTURE_NOTIFY, event)
+                .await,
+        )?;
 
         Ok(())
     }
 
-    async fn unframe<Dpy: AsyncDisplay + ?Sized>(self, conn: &mut Dpy) -> Result<()> {
-        let root = conn.default_root();
+    /// Tells the client where it is after we turned down its `ConfigureRequest`, since it will be
+    /// waiting to hear back.
+    async fn notify_geometry<Dpy: AsyncDisplay + ?Sized>(self, conn: &mut Dpy) -> Result<()> {
+        let geometry = self.frame.geometry_immediate_async(conn).await?;
 
-        self.frame.unmap_async(conn).await?;
+        // the client fills the inside of its frame
+        let x = i32::from(geometry.x) + i32::from(geometry.border_width);
+        let y = i32::from(geometry.y) + i32::from(geometry.border_width);
 
-        may_not_exist(self.win.unmap_async(conn).await)?;
+        self.notify_configure(
+            conn,
+            u16::try_from(x).unwrap_or(0),
+            u16::try_from(y).unwrap_or(0),
+            geometry.width,
+            geometry.height,
+        )
+        .await
+    }
 
-        may_not_exist(self.win.reparent_async(conn, root, 0, 0).await)?;
-        // no longer related to us, remove from save set
-        may_not_exist(self.win.change_save_set_async(conn, SetMode::Delete).await)?;
+    /// Sends the client one of the messages from `WM_PROTOCOLS`, with the `time` of the event that
+    /// made us send it.
+    async fn send_protocol<Dpy: AsyncDisplay + ?Sized>(
+        self,
+        conn: &mut Dpy,
+        atoms: &Atoms,
+        protocol: Atom,
+        time: u32,
+    ) -> Result<()> {
+        // only `_NET_WM_PING` uses the window, which it sends back to us
+        let data: Vec<u8> = [protocol.xid, time, self.win.xid, 0, 0]
+            .iter()
+            .flat_map(|value| value.to_ne_bytes())
+            .collect();
+
+        let event = Event::ClientMessage(ClientMessageEvent {
+            event_type: 33, // constant, check x protocol docs
+            format: 32,
+            sequence: 0,
+            window: self.win,
+            ty: atoms.wm_protocols,
+            data: ClientMessageData::from_bytes(&data).unwrap().0,
+        });
 
-        self.frame.free_async(conn).await?;
+        may_not_exist(
+            conn.send_event_async(self.win, EventMask::default(), event)
+                .await,
+        )?;
 
         Ok(())
     }
 
-    async fn kill_client<Dpy: AsyncDisplay + ?Sized>(self, conn: &mut Dpy) -> Result<()> {
-        struct ListOfAtom(Vec<Atom>);
-
-        impl AsByteSequence for ListOfAtom {
-            fn size(&self) -> usize {
-                unimplemented!()
-            }
-            fn as_bytes(&self, _: &mut [u8]) -> usize {
-                unimplemented!()
-            }
-
-            fn from_bytes(mut bytes: &[u8]) -> Option<(Self, usize)> {
-                let mut index = 0;
-                let mut vec = Vec::new();
-
-                while let Some((atom, index2)) = Atom::from_bytes(bytes) {
-                    vec.push(atom);
-                    index += index2;
-                    bytes = &bytes[index2..];
-                }
-
-                Some((Self(vec), index))
-            }
-        }
+    /// Sets the `WM_STATE` of the client, which may have gone away already.
+    async fn set_state<Dpy: AsyncDisplay + ?Sized>(
+        self,
+        conn: &mut Dpy,
+        atoms: &Atoms,
+        state: WmState,
+    ) -> Result<()> {
+        may_not_exist(set_wm_state(conn, atoms, self.win, state).await)?;
 
-        fn as_u8_slice(data: &[u32]) -> &[u8] {
-            // SAFETY: i believe in you to see that this is sound
-            unsafe {
-                slice::from_raw_parts(
-                    data.as_ptr().cast::<u8>(),
-                    data.len().checked_mul(4).unwrap(),
-                )
-            }
-        }
+        Ok(())
+    }
 
-        let wm_protocols = conn
-            .intern_atom_immediate_async("WM_PROTOCOLS", true)
-            .await?;
-        assert!(wm_protocols.xid != 0);
-        let wm_delete_window = conn
-            .intern_atom_immediate_async("WM_DELETE_WINDOW", true)
-            .await?;
-        assert!(wm_delete_window.xid != 0);
+    /// Colours the border depending on whether this is `focused_win`, or is hung.
+    async fn set_border<Dpy: AsyncDisplay + ?Sized>(
+        self,
+        conn: &mut Dpy,
+        focused_win: Window,
+    ) -> Result<()> {
+        let focused = focused_win == self.win;
 
-        let prop = self
-            .win
-            .get_property_immediate_async::<_, ListOfAtom>(
+        self.frame
+            .change_attributes_async(
                 conn,
-                wm_protocols,
-                PropertyType::Atom,
-                false,
+                WindowParameters {
+                    border_pixel: Some(if self.hung {
+                        CONFIG.hung_color()
+                    } else if focused {
+                        CONFIG.focused_color()
+                    } else {
+                        self.border_color
+                    }),
+                    ..Default::default()
+                },
             )
             .await?;
-        let protocols = prop.unwrap().0; // should never fail to parse
-
-        if protocols.contains(&wm_delete_window) {
-            let data = [wm_delete_window.xid, 0, 0, 0, 0];
-            let data_bytes = as_u8_slice(&data);
-
-            conn.send_event_async(
-                self.win,
-                EventMask::default(),
-                Event::ClientMessage(ClientMessageEvent {
-                    event_type: 33, // constant, check x protocol docs
-                    format: 32,     // tell the x server to byte-flip as if the data was a [u32]
-                    sequence: 0,    // this should be filled in for us by the x server
-                    window: self.win,
-                    ty: wm_protocols,
-                    data: ClientMessageData::from_bytes(data_bytes).unwrap().0, // why the field is private is beyond me
-                }),
+
+        Ok(())
+    }
+
+    /// Makes the frame cover `dimensions` without a border, with the client filling it, and
+    /// raises it to the top.
+    async fn configure_fullscreen<Dpy: AsyncDisplay + ?Sized>(
+        self,
+        conn: &mut Dpy,
+        dimensions: Dimensions,
+    ) -> Result<()> {
+        self.frame
+            .configure_async(
+                conn,
+                ConfigureWindowParameters {
+                    border_width: Some(0),
+                    stack_mode: Some(StackMode::Above),
+                    ..dimensions.into()
+                },
             )
             .await?;
 
-            // tokio::spawn(async {
-            //     tokio::time::sleep(Duration::from_secs(3)).await;
+        let Dimensions {
+            x,
+            y,
+            width,
+            height,
+        } = dimensions;
 
-            //     // TODO: if the client isnt responding, `free_async` the window (maybe show a popup?)
-            // });
-        } else {
-            self.win.free_async(conn).await?;
-        }
+        self.fill_frame(conn, width, height).await?;
+        self.notify_configure(conn, x, y, width, height).await
+    }
+
+    async fn map<Dpy: AsyncDisplay + ?Sized>(self, conn: &mut Dpy, atoms: &Atoms) -> Result<()> {
+        may_not_exist(self.win.map_async(conn).await)?;
+        self.frame.map_async(conn).await?;
+
+        self.set_state(conn, atoms, WmState::Normal).await
+    }
+
+    async fn unframe<Dpy: AsyncDisplay + ?Sized>(
+        self,
+        conn: &mut Dpy,
+        atoms: &Atoms,
+    ) -> Result<()> {
+        let root = conn.default_root();
+
+        self.frame.unmap_async(conn).await?;
+
+        may_not_exist(self.win.unmap_async(conn).await)?;
+        self.set_state(conn, atoms, WmState::Withdrawn).await?;
+
+        may_not_exist(self.win.reparent_async(conn, root, 0, 0).await)?;
+        // no longer related to us, remove from save set
+        may_not_exist(self.win.change_save_set_async(conn, SetMode::Delete).await)?;
+
+        self.frame.free_async(conn).await?;
 
         Ok(())
     }
 }
 
-async fn frame<Dpy: AsyncDisplay + ?Sized>(conn: &mut Dpy, win: Window) -> Result<FramedWindow> {
+/// Puts `win` into a new frame, and reads everything else we need to know about it that isn't
+/// in its `metadata`.
+async fn frame<Dpy: AsyncDisplay + ?Sized>(
+    conn: &mut Dpy,
+    atoms: &Atoms,
+    win: Window,
+    metadata: &Metadata,
+) -> Result<FramedWindow> {
     let root = conn.default_root();
 
     // here, we cant use `may_not_exist` because we need the geometry
@@ -836,14 +3096,45 @@ async fn frame<Dpy: AsyncDisplay + ?Sized>(conn: &mut Dpy, win: Window) -> Resul
         )
         .await?;
 
-    win.set_event_mask_async(conn, EventMask::BUTTON_PRESS)
+    win.set_event_mask_async(conn, EventMask::BUTTON_PRESS | EventMask::PROPERTY_CHANGE)
         .await?;
 
     may_not_exist(win.change_save_set_async(conn, SetMode::Insert).await)?;
 
     may_not_exist(win.reparent_async(conn, frame, 0, 0).await)?;
 
-    Ok(FramedWindow { frame, win })
+    let hints = get_size_hints(conn, win).await?;
+    let protocols = Protocols::new(atoms, &get_protocols(conn, atoms, win).await?);
+    let focus = FocusModel::new(metadata.hints.input, protocols.take_focus);
+
+    let framed = FramedWindow {
+        frame,
+        win,
+        hints,
+        focus,
+        protocols,
+        hung: false,
+        border_size: CONFIG.border_size(),
+        border_color: CONFIG.border_color(),
+    };
+
+    framed.set_state(conn, atoms, WmState::Normal).await?;
+
+    Ok(framed)
+}
+
+/// The name of this machine, to compare with the `WM_CLIENT_MACHINE` of clients.
+fn hostname() -> Option<String> {
+    let mut buf = [0_u8; 256];
+
+    // SAFETY: the buffer is as long as we say it is
+    if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } != 0 {
+        return None;
+    }
+
+    let len = buf.iter().position(|&byte| byte == 0).unwrap_or(buf.len());
+
+    Some(String::from_utf8_lossy(&buf[..len]).into_owned())
 }
 
 pub fn keymap(state: &mut KeyboardState) -> HashMap<Keysym, Keycode> {
@@ -851,7 +3142,7 @@ pub fn keymap(state: &mut KeyboardState) -> HashMap<Keysym, Keycode> {
     for keycode in 8..255_u8 {
         let key = state.process_keycode(keycode, KeyButMask::default());
         let keysyms = state.lookup_keysyms(keycode);
-        if key != None {
+        if key.is_some() {
             for keysym in keysyms {
                 map.insert(*keysym, keycode);
             }
@@ -0,0 +1,302 @@
+// Copyright (C) 2022 Infoshock Tech
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! The sizes a client is happy to be, from its `WM_NORMAL_HINTS`, and how it wants to be
+//! focused and closed, from its `WM_HINTS` and `WM_PROTOCOLS`.
+
+use breadx::Atom;
+
+use super::atoms::Atoms;
+
+// the flags saying which fields of `WM_SIZE_HINTS` are set
+const P_MIN_SIZE: u32 = 1 << 4;
+const P_MAX_SIZE: u32 = 1 << 5;
+const P_RESIZE_INC: u32 = 1 << 6;
+const P_ASPECT: u32 = 1 << 7;
+const P_BASE_SIZE: u32 = 1 << 8;
+
+// the flags saying which fields of `WM_HINTS` are set
+const INPUT_HINT: u32 = 1;
+const URGENCY_HINT: u32 = 1 << 8;
+
+/// What a client told us about the sizes it can be. Everything is the size of the client itself,
+/// without our borders.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SizeHints {
+    pub min: Option<(u16, u16)>,
+    pub max: Option<(u16, u16)>,
+    pub base: Option<(u16, u16)>,
+    /// The steps the client wants to grow in, like the size of a cell in a terminal.
+    pub inc: Option<(u16, u16)>,
+    /// The smallest and largest width to height ratios.
+    pub aspect: Option<(f64, f64)>,
+}
+
+impl SizeHints {
+    /// Reads the hints out of a `WM_SIZE_HINTS` property. Anything that isn't there or doesn't
+    /// make sense is left out.
+    pub fn parse(values: &[u32]) -> Self {
+        // the fields are signed, and anything negative is as good as not set
+        let field = |index: usize| {
+            values
+                .get(index)
+                .and_then(|&value| u16::try_from(value).ok())
+                .filter(|&value| value > 0)
+        };
+        let pair = |flag: u32, index: usize| {
+            let flags = values.first().copied().unwrap_or_default();
+
+            (flags & flag != 0)
+                .then(|| field(index).zip(field(index + 1)))
+                .flatten()
+        };
+
+        let aspect = pair(P_ASPECT, 11)
+            .zip(pair(P_ASPECT, 13))
+            .map(|((min_x, min_y), (max_x, max_y))| {
+                (
+                    f64::from(min_x) / f64::from(min_y),
+                    f64::from(max_x) / f64::from(max_y),
+                )
+            })
+            .filter(|&(min, max)| min <= max);
+
+        Self {
+            min: pair(P_MIN_SIZE, 5),
+            max: pair(P_MAX_SIZE, 7),
+            base: pair(P_BASE_SIZE, 15),
+            inc: pair(P_RESIZE_INC, 9),
+            aspect,
+        }
+    }
+
+    /// The closest size to `width` by `height` that the client is happy with. It's never bigger
+    /// than that, unless the client's minimum size is.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn constrain(&self, width: u16, height: u16) -> (u16, u16) {
+        // the icccm says each of these stands in for the other if only one is given
+        let (min_width, min_height) = self.min.or(self.base).unwrap_or((1, 1));
+        let (base_width, base_height) = self.base.or(self.min).unwrap_or_default();
+
+        let (mut width, mut height) = (width, height);
+
+        if let Some((max_width, max_height)) = self.max {
+            width = width.min(max_width);
+            height = height.min(max_height);
+        }
+
+        if let Some((min_aspect, max_aspect)) = self.aspect {
+            // only here is the base size not replaced by the minimum one
+            let (base_width, base_height) = self.base.unwrap_or_default();
+            let aspect_width = f64::from(width.saturating_sub(base_width));
+            let aspect_height = f64::from(height.saturating_sub(base_height));
+
+            if aspect_width > 0.0 && aspect_height > 0.0 {
+                let ratio = aspect_width / aspect_height;
+
+                // shrink whichever side is too long
+                if ratio > max_aspect {
+                    width = base_width.saturating_add((aspect_height * max_aspect) as u16);
+                } else if ratio < min_aspect {
+                    height = base_height.saturating_add((aspect_width / min_aspect) as u16);
+                }
+            }
+        }
+
+        if let Some((inc_width, inc_height)) = self.inc {
+            let snap = |length: u16, base: u16, inc: u16| {
+                if length < base {
+                    return length;
+                }
+
+                length - (length - base) % inc
+            };
+
+            width = snap(width, base_width, inc_width);
+            height = snap(height, base_height, inc_height);
+        }
+
+        (width.max(min_width), height.max(min_height))
+    }
+}
+
+/// The parts of `WM_HINTS` we care about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WmHints {
+    /// Whether the client wants us to give it the focus. Clients that don't say do.
+    pub input: bool,
+    /// Whether the client wants the user's attention.
+    pub urgent: bool,
+}
+
+impl Default for WmHints {
+    fn default() -> Self {
+        Self {
+            input: true,
+            urgent: false,
+        }
+    }
+}
+
+impl WmHints {
+    /// Reads the hints out of a `WM_HINTS` property, which starts with the flags.
+    pub fn parse(values: &[u32]) -> Self {
+        let flags = values.first().copied().unwrap_or_default();
+
+        Self {
+            input: match values.get(1) {
+                Some(&input) if flags & INPUT_HINT != 0 => input != 0,
+                _ => true,
+            },
+            urgent: flags & URGENCY_HINT != 0,
+        }
+    }
+}
+
+/// The messages from `WM_PROTOCOLS` that a client understands, out of the ones we send.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Protocols {
+    pub take_focus: bool,
+    pub delete_window: bool,
+    /// `_NET_WM_PING`, which tells us whether a client that was asked to close is hung.
+    pub ping: bool,
+}
+
+impl Protocols {
+    pub fn new(atoms: &Atoms, protocols: &[Atom]) -> Self {
+        Self {
+            take_focus: protocols.contains(&atoms.wm_take_focus),
+            delete_window: protocols.contains(&atoms.wm_delete_window),
+            ping: protocols.contains(&atoms.net_wm_ping),
+        }
+    }
+}
+
+/// How a client wants to get the input focus. These are the four models from the ICCCM, which
+/// come from the `input` field of `WM_HINTS` and whether `WM_PROTOCOLS` has `WM_TAKE_FOCUS`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FocusModel {
+    /// Never wants the focus.
+    NoInput,
+    /// Gets the focus from us, and doesn't do anything about it itself.
+    #[default]
+    Passive,
+    /// Gets the focus from us, and may pass it on to its other windows.
+    LocallyActive,
+    /// Only ever takes the focus itself, when we ask it to.
+    GloballyActive,
+}
+
+impl FocusModel {
+    pub fn new(input: bool, take_focus: bool) -> Self {
+        match (input, take_focus) {
+            (false, false) => Self::NoInput,
+            (true, false) => Self::Passive,
+            (true, true) => Self::LocallyActive,
+            (false, true) => Self::GloballyActive,
+        }
+    }
+
+    /// Whether we should give the client the focus ourselves.
+    pub fn gets_focus(self) -> bool {
+        matches!(self, Self::Passive | Self::LocallyActive)
+    }
+
+    /// Whether the client wants a `WM_TAKE_FOCUS` message when it's focused.
+    pub fn takes_focus(self) -> bool {
+        matches!(self, Self::LocallyActive | Self::GloballyActive)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn size() -> impl Strategy<Value = (u16, u16)> {
+        (1..2000_u16, 1..2000_u16)
+    }
+
+    fn hints() -> impl Strategy<Value = SizeHints> {
+        (
+            proptest::option::of(size()),
+            proptest::option::of(size()),
+            proptest::option::of((0..100_u16, 0..100_u16)),
+            proptest::option::of((1..30_u16, 1..30_u16)),
+        )
+            .prop_map(|(min, max, base, inc)| SizeHints {
+                min,
+                max,
+                base,
+                inc,
+                aspect: None,
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn constrained_sizes_follow_the_hints(hints in hints(), (width, height) in size()) {
+            let (constrained_width, constrained_height) = hints.constrain(width, height);
+            let (min_width, min_height) = hints.min.or(hints.base).unwrap_or((1, 1));
+
+            // the minimum size wins over everything else
+            prop_assert!(constrained_width >= min_width && constrained_height >= min_height);
+
+            if constrained_width > min_width {
+                prop_assert!(constrained_width <= width);
+
+                if let Some((max_width, _)) = hints.max {
+                    prop_assert!(constrained_width <= max_width);
+                }
+
+                if let Some((inc_width, _)) = hints.inc {
+                    let (base_width, _) = hints.base.or(hints.min).unwrap_or_default();
+
+                    prop_assert!(
+                        constrained_width < base_width
+                            || (constrained_width - base_width) % inc_width == 0
+                    );
+                }
+            }
+
+            if constrained_height > min_height {
+                prop_assert!(constrained_height <= height);
+
+                if let Some((_, max_height)) = hints.max {
+                    prop_assert!(constrained_height <= max_height);
+                }
+            }
+        }
+
+        #[test]
+        fn aspect_ratios_are_kept(
+            (width, height) in size(),
+            (min_aspect, max_aspect) in (0.2..1.0_f64, 1.0..5.0_f64),
+        ) {
+            let hints = SizeHints {
+                aspect: Some((min_aspect, max_aspect)),
+                ..SizeHints::default()
+            };
+
+            let (constrained_width, constrained_height) = hints.constrain(width, height);
+            let ratio = f64::from(constrained_width) / f64::from(constrained_height);
+
+            prop_assert!(constrained_width <= width && constrained_height <= height);
+            // rounding down can take off at most one pixel
+            prop_assert!(ratio <= max_aspect + 1.0 / f64::from(constrained_height));
+            prop_assert!(ratio >= min_aspect - 1.0 / f64::from(constrained_height));
+        }
+    }
+}
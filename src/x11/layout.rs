@@ -0,0 +1,174 @@
+// Copyright (C) 2022 Infoshock Tech
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::str::FromStr;
+
+use super::tree::{Dimensions, Directionality};
+use crate::{XcrabError, CONFIG};
+
+/// An automatic layout, which places clients without regard for how the tree is split up.
+pub trait Layout {
+    /// Places `count` clients inside `area`, with `gap_size` pixels between them. Clients are
+    /// given in the order they appear in the tree, and the returned `Vec` has one entry per
+    /// client in that same order.
+    fn arrange(&self, area: Dimensions, count: usize, gap_size: u16) -> Vec<Dimensions>;
+}
+
+/// Which layout is used to place clients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LayoutKind {
+    /// The manual split tree.
+    #[default]
+    Tree,
+    MasterStack,
+    Monocle,
+    Grid,
+    Spiral,
+    Dwindle,
+}
+
+impl LayoutKind {
+    /// The automatic layout to use, or `None` for the manual tree.
+    pub fn layout(self) -> Option<Box<dyn Layout>> {
+        match self {
+            Self::Tree => None,
+            Self::MasterStack => Some(Box::new(MasterStack {
+                ratio: CONFIG.master_ratio(),
+            })),
+            Self::Monocle => Some(Box::new(Monocle)),
+            Self::Grid => Some(Box::new(Grid)),
+            Self::Spiral => Some(Box::new(Spiral { dwindle: false })),
+            Self::Dwindle => Some(Box::new(Spiral { dwindle: true })),
+        }
+    }
+}
+
+impl FromStr for LayoutKind {
+    type Err = XcrabError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "tree" | "manual" => Ok(Self::Tree),
+            "master-stack" | "master" | "tile" => Ok(Self::MasterStack),
+            "monocle" => Ok(Self::Monocle),
+            "grid" => Ok(Self::Grid),
+            "spiral" => Ok(Self::Spiral),
+            "dwindle" => Ok(Self::Dwindle),
+            _ => Err(format!("Unknown layout: {s}").into()),
+        }
+    }
+}
+
+/// The first client on the left taking up `ratio` of the width, the rest stacked on the right.
+pub struct MasterStack {
+    pub ratio: f64,
+}
+
+impl Layout for MasterStack {
+    fn arrange(&self, area: Dimensions, count: usize, gap_size: u16) -> Vec<Dimensions> {
+        if count <= 1 {
+            return vec![area; count];
+        }
+
+        let ratio = self.ratio.clamp(0.05, 0.95);
+        let halves = area.split(Directionality::Horizontal, &[ratio, 1.0 - ratio], gap_size);
+
+        let mut dimensions = vec![halves[0]];
+        dimensions.extend(halves[1].split(
+            Directionality::Vertical,
+            &vec![1.0; count - 1],
+            gap_size,
+        ));
+
+        dimensions
+    }
+}
+
+/// Every client fills the whole area, with the focused one on top.
+pub struct Monocle;
+
+impl Layout for Monocle {
+    fn arrange(&self, area: Dimensions, count: usize, _gap_size: u16) -> Vec<Dimensions> {
+        vec![area; count]
+    }
+}
+
+/// Rows of equally sized clients, as close to square as possible. The last row takes up the
+/// slack if the clients don't divide evenly.
+pub struct Grid;
+
+impl Layout for Grid {
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    fn arrange(&self, area: Dimensions, count: usize, gap_size: u16) -> Vec<Dimensions> {
+        if count == 0 {
+            return Vec::new();
+        }
+
+        let columns = (count as f64).sqrt().ceil() as usize;
+        let rows = count.div_ceil(columns);
+
+        area.split(Directionality::Vertical, &vec![1.0; rows], gap_size)
+            .into_iter()
+            .enumerate()
+            .flat_map(|(row, dimensions)| {
+                let in_row = columns.min(count - row * columns);
+                dimensions.split(Directionality::Horizontal, &vec![1.0; in_row], gap_size)
+            })
+            .collect()
+    }
+}
+
+/// Each client takes half of the space left over by the ones before it. In dwindle mode the
+/// remaining space always goes to the bottom right, otherwise it winds inwards in a spiral.
+pub struct Spiral {
+    pub dwindle: bool,
+}
+
+impl Layout for Spiral {
+    fn arrange(&self, mut area: Dimensions, count: usize, gap_size: u16) -> Vec<Dimensions> {
+        let mut dimensions = Vec::with_capacity(count);
+
+        for i in 0..count {
+            if i + 1 == count {
+                dimensions.push(area);
+                break;
+            }
+
+            let directionality = if i % 2 == 0 {
+                Directionality::Horizontal
+            } else {
+                Directionality::Vertical
+            };
+
+            let halves = area.split(directionality, &[1.0, 1.0], gap_size);
+
+            // going right, down, left, up, ...
+            let (this, rest) = if !self.dwindle && i % 4 >= 2 {
+                (halves[1], halves[0])
+            } else {
+                (halves[0], halves[1])
+            };
+
+            dimensions.push(this);
+            area = rest;
+        }
+
+        dimensions
+    }
+}
@@ -0,0 +1,155 @@
+// Copyright (C) 2022 Infoshock Tech
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! What we know about a client from its properties. It's read once when the client is framed
+//! and kept up to date from `PropertyNotify`s, so nothing else has to go to the server for it.
+
+use breadx::{AsyncDisplay, Atom, Window};
+
+use super::{
+    atoms::Atoms,
+    hints::WmHints,
+    policy::WindowType,
+    property::{
+        get_class, get_pid, get_role, get_title, get_transient_for, get_window_types, get_wm_hints,
+        WM_CLASS, WM_HINTS, WM_NAME, WM_TRANSIENT_FOR,
+    },
+};
+use crate::Result;
+
+/// The properties of a client that the rest of the window manager cares about.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+    /// `_NET_WM_NAME`, or `WM_NAME` if it doesn't have one.
+    pub title: String,
+    /// The two halves of `WM_CLASS`.
+    pub instance: String,
+    pub class: String,
+    /// `WM_WINDOW_ROLE`, which tells apart the different kinds of window of one application.
+    pub role: String,
+    /// `_NET_WM_PID`, the process the client belongs to on its machine.
+    pub pid: Option<u32>,
+    pub hints: WmHints,
+    /// `_NET_WM_WINDOW_TYPE`, most specific first.
+    pub window_types: Vec<Atom>,
+    /// `WM_TRANSIENT_FOR`, the window this one is a dialog (or the like) for.
+    pub transient_for: Option<Window>,
+}
+
+/// A part of `Metadata`, for saying which of them changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Title,
+    Class,
+    Role,
+    Pid,
+    Hints,
+    WindowType,
+    TransientFor,
+}
+
+impl Change {
+    const ALL: [Self; 7] = [
+        Self::Title,
+        Self::Class,
+        Self::Role,
+        Self::Pid,
+        Self::Hints,
+        Self::WindowType,
+        Self::TransientFor,
+    ];
+
+    /// The part of `Metadata` that comes from `property`, if any.
+    pub fn of(atoms: &Atoms, property: Atom) -> Option<Self> {
+        if property == WM_NAME || property == atoms.net_wm_name {
+            Some(Self::Title)
+        } else if property == WM_CLASS {
+            Some(Self::Class)
+        } else if property == atoms.wm_window_role {
+            Some(Self::Role)
+        } else if property == atoms.net_wm_pid {
+            Some(Self::Pid)
+        } else if property == WM_HINTS {
+            Some(Self::Hints)
+        } else if property == atoms.net_wm_window_type {
+            Some(Self::WindowType)
+        } else if property == WM_TRANSIENT_FOR {
+            Some(Self::TransientFor)
+        } else {
+            None
+        }
+    }
+}
+
+impl Metadata {
+    pub async fn read<Dpy: AsyncDisplay + ?Sized>(
+        conn: &mut Dpy,
+        atoms: &Atoms,
+        win: Window,
+    ) -> Result<Self> {
+        let mut metadata = Self::default();
+
+        for change in Change::ALL {
+            metadata.refresh(conn, atoms, win, change).await?;
+        }
+
+        Ok(metadata)
+    }
+
+    /// Re-reads the part of the metadata of `win` that `change` is about, and returns whether it
+    /// is any different now.
+    pub async fn refresh<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        atoms: &Atoms,
+        win: Window,
+        change: Change,
+    ) -> Result<bool> {
+        Ok(match change {
+            Change::Title => replace(&mut self.title, get_title(conn, atoms, win).await?),
+            Change::Class => {
+                let (instance, class) = get_class(conn, win).await?;
+
+                // both have to be replaced, so don't short circuit
+                replace(&mut self.instance, instance) | replace(&mut self.class, class)
+            }
+            Change::Role => replace(&mut self.role, get_role(conn, atoms, win).await?),
+            Change::Pid => replace(&mut self.pid, get_pid(conn, atoms, win).await?),
+            Change::Hints => replace(&mut self.hints, get_wm_hints(conn, win).await?),
+            Change::WindowType => replace(
+                &mut self.window_types,
+                get_window_types(conn, atoms, win).await?,
+            ),
+            Change::TransientFor => {
+                replace(&mut self.transient_for, get_transient_for(conn, win).await?)
+            }
+        })
+    }
+
+    /// The type of the window, from its `_NET_WM_WINDOW_TYPE` and `WM_TRANSIENT_FOR`.
+    pub fn window_type(&self, atoms: &Atoms) -> WindowType {
+        WindowType::of(atoms, &self.window_types, self.transient_for.is_some())
+    }
+}
+
+/// Puts `value` in `field`, returning whether that changed anything.
+fn replace<T: PartialEq>(field: &mut T, value: T) -> bool {
+    if *field == value {
+        return false;
+    }
+
+    *field = value;
+    true
+}
@@ -13,4 +13,14 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+pub mod atoms;
 pub mod client;
+mod hints;
+pub mod layout;
+mod metadata;
+mod output;
+pub mod policy;
+mod property;
+pub mod rules;
+mod tab_bar;
+pub mod tree;
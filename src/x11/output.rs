@@ -0,0 +1,286 @@
+// Copyright (C) 2022 Infoshock Tech
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use breadx::{
+    auto::{randr, xinerama, xproto::QueryExtensionRequest},
+    AsyncDisplay, AsyncDisplayExt,
+};
+
+use super::tree::Dimensions;
+use crate::Result;
+
+/// The space a dock keeps for itself along the edges of the screen, which nothing else is put
+/// over. Each edge only covers the part of the screen between its start and end (inclusive).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Strut {
+    left: (u32, u32, u32),
+    right: (u32, u32, u32),
+    top: (u32, u32, u32),
+    bottom: (u32, u32, u32),
+}
+
+impl Strut {
+    /// Reads a `_NET_WM_STRUT_PARTIAL`, or a `_NET_WM_STRUT` which covers the whole length of each
+    /// edge.
+    pub fn parse(values: &[u32]) -> Self {
+        let value = |index: usize| values.get(index).copied();
+        let edge = |index: usize| {
+            let width = value(index).unwrap_or_default();
+            let start = value(4 + index * 2).unwrap_or(0);
+            let end = value(5 + index * 2).unwrap_or(u32::MAX);
+
+            (width, start, end)
+        };
+
+        Self {
+            left: edge(0),
+            right: edge(1),
+            top: edge(2),
+            bottom: edge(3),
+        }
+    }
+
+    /// Takes the parts of `area`, a monitor on `screen`, that this strut keeps for itself away.
+    pub fn reserve(&self, area: Dimensions, screen: Dimensions) -> Dimensions {
+        let screen_left = u32::from(screen.x);
+        let screen_top = u32::from(screen.y);
+        let screen_right = screen_left + u32::from(screen.width);
+        let screen_bottom = screen_top + u32::from(screen.height);
+
+        let area_left = u32::from(area.x);
+        let area_top = u32::from(area.y);
+        let area_right = area_left + u32::from(area.width);
+        let area_bottom = area_top + u32::from(area.height);
+
+        // whether an edge is there at all and covers any of `low..high`
+        let covers = |(width, start, end): (u32, u32, u32), low: u32, high: u32| {
+            width > 0 && start < high && end >= low
+        };
+
+        let mut left = area_left;
+        let mut top = area_top;
+        let mut right = area_right;
+        let mut bottom = area_bottom;
+
+        if covers(self.left, area_top, area_bottom) {
+            left = left.max(screen_left.saturating_add(self.left.0));
+        }
+        if covers(self.right, area_top, area_bottom) {
+            right = right.min(screen_right.saturating_sub(self.right.0));
+        }
+        if covers(self.top, area_left, area_right) {
+            top = top.max(screen_top.saturating_add(self.top.0));
+        }
+        if covers(self.bottom, area_left, area_right) {
+            bottom = bottom.min(screen_bottom.saturating_sub(self.bottom.0));
+        }
+
+        // a strut bigger than the monitor leaves nothing of it, rather than going past its edges
+        let left = left.min(area_right);
+        let top = top.min(area_bottom);
+        let right = right.clamp(left, area_right);
+        let bottom = bottom.clamp(top, area_bottom);
+
+        // everything is between the edges of `area`, so it all fits back into a `u16`
+        Dimensions {
+            x: u16::try_from(left).unwrap_or(u16::MAX),
+            y: u16::try_from(top).unwrap_or(u16::MAX),
+            width: u16::try_from(right - left).unwrap_or(u16::MAX),
+            height: u16::try_from(bottom - top).unwrap_or(u16::MAX),
+        }
+    }
+}
+
+/// Finds the area of every monitor, with the primary one first.
+///
+/// This asks `RandR`, then Xinerama if `RandR` is missing or too old, and if neither of them know
+/// anything treats the whole root window as a single monitor.
+pub async fn query_outputs<Dpy: AsyncDisplay + ?Sized>(conn: &mut Dpy) -> Result<Vec<Dimensions>> {
+    // the extensions not being there isn't an error for us, we just try the next thing
+    if let Ok(outputs) = query_randr(conn).await {
+        if !outputs.is_empty() {
+            return Ok(outputs);
+        }
+    }
+
+    if let Ok(outputs) = query_xinerama(conn).await {
+        if !outputs.is_empty() {
+            return Ok(outputs);
+        }
+    }
+
+    let geometry = conn.default_root().geometry_immediate_async(conn).await?;
+
+    Ok(vec![Dimensions {
+        x: u16::try_from(geometry.x).unwrap_or(0),
+        y: u16::try_from(geometry.y).unwrap_or(0),
+        width: geometry.width,
+        height: geometry.height,
+    }])
+}
+
+/// Asks `RandR` to tell us whenever the monitors change. Returns the code of its first event,
+/// which the events we get are numbered from, or `None` if the server doesn't have `RandR`.
+pub async fn select_output_events<Dpy: AsyncDisplay + ?Sized>(
+    conn: &mut Dpy,
+) -> Result<Option<u8>> {
+    // `query_extension_immediate_async` panics if the extension isn't there, so do it ourselves
+    let extension = conn
+        .exchange_request_async(QueryExtensionRequest {
+            name: "RANDR".into(),
+            ..Default::default()
+        })
+        .await?;
+
+    if !extension.present {
+        return Ok(None);
+    }
+
+    let mut enable = randr::NotifyMask::default();
+    enable
+        .set_screen_change(true)
+        .set_crtc_change(true)
+        .set_output_change(true);
+
+    conn.exchange_request_async(randr::SelectInputRequest {
+        window: conn.default_root(),
+        enable,
+        ..Default::default()
+    })
+    .await?;
+
+    Ok(Some(extension.first_event))
+}
+
+async fn query_randr<Dpy: AsyncDisplay + ?Sized>(conn: &mut Dpy) -> Result<Vec<Dimensions>> {
+    // monitors were added in 1.5, and the server wants to know which version we speak
+    let version = conn
+        .exchange_request_async(randr::QueryVersionRequest {
+            major_version: 1,
+            minor_version: 5,
+            ..Default::default()
+        })
+        .await?;
+
+    if (version.major_version, version.minor_version) < (1, 5) {
+        return Ok(Vec::new());
+    }
+
+    let reply = conn
+        .exchange_request_async(randr::GetMonitorsRequest {
+            window: conn.default_root(),
+            get_active: true,
+            ..Default::default()
+        })
+        .await?;
+
+    let mut monitors: Vec<_> = reply
+        .monitors
+        .iter()
+        .filter(|monitor| monitor.width > 0 && monitor.height > 0)
+        .map(|monitor| {
+            (
+                monitor.primary,
+                Dimensions {
+                    x: u16::try_from(monitor.x).unwrap_or(0),
+                    y: u16::try_from(monitor.y).unwrap_or(0),
+                    width: monitor.width,
+                    height: monitor.height,
+                },
+            )
+        })
+        .collect();
+
+    // stable, so the rest stay in the order the server gave them to us
+    monitors.sort_by_key(|&(primary, _)| !primary);
+
+    Ok(monitors
+        .into_iter()
+        .map(|(_, dimensions)| dimensions)
+        .collect())
+}
+
+async fn query_xinerama<Dpy: AsyncDisplay + ?Sized>(conn: &mut Dpy) -> Result<Vec<Dimensions>> {
+    let active = conn
+        .exchange_request_async(xinerama::IsActiveRequest::default())
+        .await?;
+
+    if active.state == 0 {
+        return Ok(Vec::new());
+    }
+
+    let reply = conn
+        .exchange_request_async(xinerama::QueryScreensRequest::default())
+        .await?;
+
+    Ok(reply
+        .screen_info
+        .iter()
+        .map(|screen| Dimensions {
+            x: u16::try_from(screen.x_org).unwrap_or(0),
+            y: u16::try_from(screen.y_org).unwrap_or(0),
+            width: screen.width,
+            height: screen.height,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn reserved_areas_stay_inside_the_output(
+            values in proptest::collection::vec(any::<u32>(), 0..=12),
+            area in Dimensions::strategy(0..4000_u16, 0..4000_u16),
+            screen in Dimensions::strategy(0..4000_u16, 0..4000_u16),
+        ) {
+            let reserved = Strut::parse(&values).reserve(area, screen);
+
+            prop_assert!(reserved.x >= area.x && reserved.y >= area.y);
+            prop_assert!(
+                u32::from(reserved.x) + u32::from(reserved.width)
+                    <= u32::from(area.x) + u32::from(area.width)
+            );
+            prop_assert!(
+                u32::from(reserved.y) + u32::from(reserved.height)
+                    <= u32::from(area.y) + u32::from(area.height)
+            );
+        }
+
+        #[test]
+        fn full_struts_take_their_edge(left in 0..500_u16, top in 0..500_u16) {
+            let screen = Dimensions {
+                x: 0,
+                y: 0,
+                width: 1920,
+                height: 1080,
+            };
+            let strut = Strut::parse(&[left.into(), 0, top.into(), 0]);
+
+            prop_assert_eq!(
+                strut.reserve(screen, screen),
+                Dimensions {
+                    x: left,
+                    y: top,
+                    width: 1920 - left,
+                    height: 1080 - top,
+                }
+            );
+        }
+    }
+}
@@ -0,0 +1,361 @@
+// Copyright (C) 2022 Infoshock Tech
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! What we do with a window, depending on its `_NET_WM_WINDOW_TYPE`.
+
+use breadx::Atom;
+use serde::Deserialize;
+use std::str::FromStr;
+
+use super::{atoms::Atoms, tree::Dimensions};
+use crate::XcrabError;
+
+/// The types of window from the EWMH, named like `_NET_WM_WINDOW_TYPE_DROPDOWN_MENU` becomes
+/// `dropdown-menu` in the config.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum WindowType {
+    Normal,
+    Dialog,
+    Splash,
+    Utility,
+    Toolbar,
+    Menu,
+    DropdownMenu,
+    PopupMenu,
+    Tooltip,
+    Notification,
+    Combo,
+    Dnd,
+    Dock,
+    Desktop,
+}
+
+impl WindowType {
+    /// The first of `types` (which go from most to least specific) that we know about. Windows
+    /// without one are normal, unless they are `transient` for another window, which makes them
+    /// dialogs.
+    pub fn of(atoms: &Atoms, types: &[Atom], transient: bool) -> Self {
+        let known = [
+            (atoms.net_wm_window_type_normal, Self::Normal),
+            (atoms.net_wm_window_type_dialog, Self::Dialog),
+            (atoms.net_wm_window_type_splash, Self::Splash),
+            (atoms.net_wm_window_type_utility, Self::Utility),
+            (atoms.net_wm_window_type_toolbar, Self::Toolbar),
+            (atoms.net_wm_window_type_menu, Self::Menu),
+            (atoms.net_wm_window_type_dropdown_menu, Self::DropdownMenu),
+            (atoms.net_wm_window_type_popup_menu, Self::PopupMenu),
+            (atoms.net_wm_window_type_tooltip, Self::Tooltip),
+            (atoms.net_wm_window_type_notification, Self::Notification),
+            (atoms.net_wm_window_type_combo, Self::Combo),
+            (atoms.net_wm_window_type_dnd, Self::Dnd),
+            (atoms.net_wm_window_type_dock, Self::Dock),
+            (atoms.net_wm_window_type_desktop, Self::Desktop),
+        ];
+
+        let fallback = if transient {
+            Self::Dialog
+        } else {
+            Self::Normal
+        };
+
+        types
+            .iter()
+            .find_map(|&ty| known.iter().find(|&&(atom, _)| atom == ty))
+            .map_or(fallback, |&(_, ty)| ty)
+    }
+}
+
+impl FromStr for WindowType {
+    type Err = XcrabError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        #[allow(clippy::enum_glob_use)]
+        use WindowType::*;
+
+        match s.to_ascii_lowercase().replace('_', "-").as_str() {
+            "normal" => Ok(Normal),
+            "dialog" => Ok(Dialog),
+            "splash" => Ok(Splash),
+            "utility" => Ok(Utility),
+            "toolbar" => Ok(Toolbar),
+            "menu" => Ok(Menu),
+            "dropdown-menu" => Ok(DropdownMenu),
+            "popup-menu" => Ok(PopupMenu),
+            "tooltip" => Ok(Tooltip),
+            "notification" => Ok(Notification),
+            "combo" => Ok(Combo),
+            "dnd" => Ok(Dnd),
+            "dock" => Ok(Dock),
+            "desktop" => Ok(Desktop),
+            _ => Err(format!("Unknown window type: {s}").into()),
+        }
+    }
+}
+
+/// How much we have to do with a window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Manage {
+    /// Framed and put in the tree.
+    Tile,
+    /// Framed and put in the floating layer.
+    Float,
+    /// Mapped as it is, with its struts kept free.
+    Dock,
+    /// Mapped as it is and forgotten about.
+    Ignore,
+}
+
+/// Where a window that isn't tiled goes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Placement {
+    /// In the middle of the window it is transient for, or of the output.
+    Center,
+    /// In the top right corner of the output.
+    TopRight,
+    /// Covering the whole output.
+    Fill,
+    /// Wherever the window put itself.
+    Keep,
+}
+
+/// Where a window we leave alone goes in the stack. Framed windows are stacked by us.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Layer {
+    Normal,
+    Above,
+    Below,
+}
+
+/// Everything we decide about a window when it's mapped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Policy {
+    pub manage: Manage,
+    /// Whether the window is focused when it shows up.
+    pub focus: bool,
+    pub placement: Placement,
+    pub layer: Layer,
+}
+
+impl Policy {
+    /// What we do with windows of type `ty` unless the config says otherwise.
+    pub fn default_for(ty: WindowType) -> Self {
+        #[allow(clippy::enum_glob_use)]
+        use WindowType::*;
+
+        let (manage, focus, placement, layer) = match ty {
+            Normal => (Manage::Tile, true, Placement::Center, Layer::Normal),
+            Dialog | Utility | Toolbar => (Manage::Float, true, Placement::Center, Layer::Normal),
+            Splash => (Manage::Float, false, Placement::Center, Layer::Normal),
+            Menu | DropdownMenu | PopupMenu | Tooltip | Combo | Dnd => {
+                (Manage::Ignore, false, Placement::Keep, Layer::Above)
+            }
+            Notification => (Manage::Ignore, false, Placement::TopRight, Layer::Above),
+            Dock => (Manage::Dock, false, Placement::Keep, Layer::Normal),
+            Desktop => (Manage::Ignore, false, Placement::Fill, Layer::Below),
+        };
+
+        Self {
+            manage,
+            focus,
+            placement,
+            layer,
+        }
+    }
+
+    /// Replaces the parts of this policy that `overrides` has.
+    pub fn with(self, overrides: &PolicyOverride) -> Self {
+        Self {
+            manage: overrides.manage.unwrap_or(self.manage),
+            focus: overrides.focus.unwrap_or(self.focus),
+            placement: overrides.placement.unwrap_or(self.placement),
+            layer: overrides.layer.unwrap_or(self.layer),
+        }
+    }
+
+    /// Where a window of `size` that is currently at `position` goes on an output covering
+    /// `area`. `parent` is the window it is transient for, if any.
+    pub fn place(
+        self,
+        (width, height): (u16, u16),
+        (x, y): (u16, u16),
+        area: Dimensions,
+        parent: Option<Dimensions>,
+    ) -> Dimensions {
+        match self.placement {
+            Placement::Center => parent.unwrap_or(area).center(width, height),
+            Placement::TopRight => Dimensions {
+                x: area.x.saturating_add(area.width.saturating_sub(width)),
+                y: area.y,
+                width,
+                height,
+            },
+            Placement::Fill => area,
+            Placement::Keep => Dimensions {
+                x,
+                y,
+                width,
+                height,
+            },
+        }
+    }
+}
+
+/// The parts of a policy that the config changes for some window type.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct PolicyOverride {
+    manage: Option<Manage>,
+    focus: Option<bool>,
+    placement: Option<Placement>,
+    layer: Option<Layer>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// Every window type with its name in the config, in the order `WindowType::of` prefers
+    /// them.
+    const TYPES: [(WindowType, &str); 14] = [
+        (WindowType::Normal, "normal"),
+        (WindowType::Dialog, "dialog"),
+        (WindowType::Splash, "splash"),
+        (WindowType::Utility, "utility"),
+        (WindowType::Toolbar, "toolbar"),
+        (WindowType::Menu, "menu"),
+        (WindowType::DropdownMenu, "dropdown-menu"),
+        (WindowType::PopupMenu, "popup-menu"),
+        (WindowType::Tooltip, "tooltip"),
+        (WindowType::Notification, "notification"),
+        (WindowType::Combo, "combo"),
+        (WindowType::Dnd, "dnd"),
+        (WindowType::Dock, "dock"),
+        (WindowType::Desktop, "desktop"),
+    ];
+
+    /// The atom we give the type at `index` of `TYPES`. Anything past the end is an atom for a
+    /// type we don't know about.
+    fn type_atom(index: usize) -> Atom {
+        Atom::const_from_xid(u32::try_from(index).unwrap() + 1)
+    }
+
+    fn atoms() -> Atoms {
+        Atoms {
+            net_wm_window_type_normal: type_atom(0),
+            net_wm_window_type_dialog: type_atom(1),
+            net_wm_window_type_splash: type_atom(2),
+            net_wm_window_type_utility: type_atom(3),
+            net_wm_window_type_toolbar: type_atom(4),
+            net_wm_window_type_menu: type_atom(5),
+            net_wm_window_type_dropdown_menu: type_atom(6),
+            net_wm_window_type_popup_menu: type_atom(7),
+            net_wm_window_type_tooltip: type_atom(8),
+            net_wm_window_type_notification: type_atom(9),
+            net_wm_window_type_combo: type_atom(10),
+            net_wm_window_type_dnd: type_atom(11),
+            net_wm_window_type_dock: type_atom(12),
+            net_wm_window_type_desktop: type_atom(13),
+            ..Atoms::default()
+        }
+    }
+
+    fn size() -> impl Strategy<Value = (u16, u16)> {
+        (0..4000_u16, 0..4000_u16)
+    }
+
+    /// Areas anywhere, including right at the edge of what `u16` can hold.
+    fn area() -> impl Strategy<Value = Dimensions> {
+        Dimensions::strategy(
+            prop_oneof![0..4000_u16, (u16::MAX - 100)..=u16::MAX],
+            0..4000_u16,
+        )
+    }
+
+    proptest! {
+        #[test]
+        fn the_first_known_type_wins(
+            indices in proptest::collection::vec(0..20_usize, 0..5),
+            transient in any::<bool>(),
+        ) {
+            let types: Vec<Atom> = indices.iter().map(|&index| type_atom(index)).collect();
+            let fallback = if transient { WindowType::Dialog } else { WindowType::Normal };
+            let expected = indices
+                .iter()
+                .find_map(|&index| TYPES.get(index))
+                .map_or(fallback, |&(ty, _)| ty);
+
+            prop_assert_eq!(WindowType::of(&atoms(), &types, transient), expected);
+        }
+
+        #[test]
+        fn type_names_ignore_case_and_underscores(
+            index in 0..TYPES.len(),
+            upper in proptest::collection::vec(any::<bool>(), 13),
+            underscore in any::<bool>(),
+        ) {
+            let (ty, name) = TYPES[index];
+            let name: String = name
+                .chars()
+                .zip(upper.iter().cycle())
+                .map(|(c, &upper)| match c {
+                    '-' if underscore => '_',
+                    c if upper => c.to_ascii_uppercase(),
+                    c => c,
+                })
+                .collect();
+
+            prop_assert_eq!(name.parse::<WindowType>().ok(), Some(ty));
+            let unknown = format!("{name}-window");
+            prop_assert!(unknown.parse::<WindowType>().is_err());
+        }
+
+        #[test]
+        fn top_right_windows_stay_in_their_corner((width, height) in size(), area in area()) {
+            let policy = Policy {
+                placement: Placement::TopRight,
+                ..Policy::default_for(WindowType::Notification)
+            };
+            let placed = policy.place((width, height), (0, 0), area, None);
+
+            prop_assert_eq!((placed.y, placed.width, placed.height), (area.y, width, height));
+            prop_assert!(placed.x >= area.x);
+
+            // it lines up with the right edge, unless that's further than x can go or it's wider
+            // than the output, in which case it sticks out on the right
+            let right = u32::from(area.x) + u32::from(area.width);
+            if width <= area.width {
+                let expected = (right - u32::from(width)).min(u32::from(u16::MAX));
+                prop_assert_eq!(u32::from(placed.x), expected);
+            } else {
+                prop_assert_eq!(placed.x, area.x);
+            }
+        }
+
+        #[test]
+        fn kept_windows_stay_where_they_are(
+            size in size(),
+            (x, y) in (any::<u16>(), any::<u16>()),
+            area in area(),
+        ) {
+            let policy = Policy::default_for(WindowType::Menu);
+            let placed = policy.place(size, (x, y), area, None);
+
+            prop_assert_eq!((placed.x, placed.y, placed.width, placed.height), (x, y, size.0, size.1));
+        }
+    }
+}
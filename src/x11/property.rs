@@ -0,0 +1,404 @@
+// Copyright (C) 2022 Infoshock Tech
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use breadx::{
+    auto::xproto::{ChangePropertyRequest, GetPropertyRequest, PropMode},
+    prelude::{PropertyFormat, PropertyType},
+    AsyncDisplay, AsyncDisplayExt, Atom, BreadError, ErrorCode, Window,
+};
+
+use super::{
+    atoms::Atoms,
+    hints::{SizeHints, WmHints},
+    output::Strut,
+};
+use crate::Result;
+
+/// `AnyPropertyType`, for when we don't care what type the property is.
+const ANY_PROPERTY_TYPE: Atom = Atom::const_from_xid(0);
+
+/// The predefined `ATOM` atom, the type of properties holding atoms.
+const ATOM: Atom = Atom::const_from_xid(4);
+
+/// The predefined `CARDINAL` atom, the type of properties holding plain numbers.
+const CARDINAL: Atom = Atom::const_from_xid(6);
+
+/// The predefined `WINDOW` atom, the type of properties holding windows.
+const WINDOW: Atom = Atom::const_from_xid(33);
+
+/// The predefined `WM_HINTS` atom, which is also its type.
+pub const WM_HINTS: Atom = Atom::const_from_xid(35);
+
+/// The predefined `WM_CLIENT_MACHINE` atom.
+const WM_CLIENT_MACHINE: Atom = Atom::const_from_xid(36);
+
+/// The predefined `WM_NAME` atom.
+pub const WM_NAME: Atom = Atom::const_from_xid(39);
+
+/// The predefined `WM_NORMAL_HINTS` atom.
+pub const WM_NORMAL_HINTS: Atom = Atom::const_from_xid(40);
+
+/// The predefined `WM_SIZE_HINTS` atom, the type of `WM_NORMAL_HINTS`.
+const WM_SIZE_HINTS: Atom = Atom::const_from_xid(41);
+
+/// The predefined `WM_CLASS` atom.
+pub const WM_CLASS: Atom = Atom::const_from_xid(67);
+
+/// The predefined `WM_TRANSIENT_FOR` atom.
+pub const WM_TRANSIENT_FOR: Atom = Atom::const_from_xid(68);
+
+/// Reads the raw bytes of a property, or `None` if it isn't set (or has a different type). A
+/// window that has gone away reads as if it had no properties at all, so whatever read them can
+/// carry on until we hear it was destroyed.
+///
+/// `Window::get_property_immediate_async` only asks for the first few bytes of the property,
+/// which isn't enough for things like titles, so we do the request ourselves.
+pub async fn get_property<Dpy: AsyncDisplay + ?Sized>(
+    conn: &mut Dpy,
+    win: Window,
+    property: Atom,
+    ty: Option<Atom>,
+) -> Result<Option<Vec<u8>>> {
+    let reply = conn
+        .exchange_request_async(GetPropertyRequest {
+            window: win,
+            property,
+            ty: ty.unwrap_or(ANY_PROPERTY_TYPE),
+            long_offset: 0,
+            // in 4 byte units, so this is plenty for anything we read
+            long_length: 4096,
+            delete: false,
+            ..Default::default()
+        })
+        .await;
+
+    let reply = match reply {
+        Ok(reply) => reply,
+        // a `Window` error
+        Err(BreadError::XProtocol {
+            error_code: ErrorCode(3),
+            ..
+        }) => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    if reply.ty.xid == 0 || ty.is_some_and(|ty| ty != reply.ty) {
+        return Ok(None);
+    }
+
+    Ok(Some(reply.value.into_owned()))
+}
+
+/// Reads the title of a window, preferring `_NET_WM_NAME` over `WM_NAME`.
+pub async fn get_title<Dpy: AsyncDisplay + ?Sized>(
+    conn: &mut Dpy,
+    atoms: &Atoms,
+    win: Window,
+) -> Result<String> {
+    let bytes = match get_property(conn, win, atoms.net_wm_name, Some(atoms.utf8_string)).await? {
+        Some(bytes) => Some(bytes),
+        None => get_property(conn, win, WM_NAME, None).await?,
+    };
+
+    Ok(bytes
+        .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+        .unwrap_or_default())
+}
+
+/// Reads the instance and class names of a window from `WM_CLASS`. Both are empty if it isn't
+/// set.
+pub async fn get_class<Dpy: AsyncDisplay + ?Sized>(
+    conn: &mut Dpy,
+    win: Window,
+) -> Result<(String, String)> {
+    let bytes = get_property(conn, win, WM_CLASS, None)
+        .await?
+        .unwrap_or_default();
+
+    // two null terminated strings, one after the other
+    let mut names = bytes
+        .split(|&byte| byte == 0)
+        .map(|name| String::from_utf8_lossy(name).into_owned());
+
+    Ok((
+        names.next().unwrap_or_default(),
+        names.next().unwrap_or_default(),
+    ))
+}
+
+/// Reads a property made up of 32 bit values, such as a list of atoms or windows.
+async fn get_u32s<Dpy: AsyncDisplay + ?Sized>(
+    conn: &mut Dpy,
+    win: Window,
+    property: Atom,
+    ty: Atom,
+) -> Result<Vec<u32>> {
+    let bytes = get_property(conn, win, property, Some(ty))
+        .await?
+        .unwrap_or_default();
+
+    // the server sends these in our byte order
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|chunk| u32::from_ne_bytes(chunk.try_into().unwrap()))
+        .collect())
+}
+
+/// Reads a property holding a list of atoms, which is empty if the property isn't set.
+pub async fn get_atoms<Dpy: AsyncDisplay + ?Sized>(
+    conn: &mut Dpy,
+    win: Window,
+    property: Atom,
+) -> Result<Vec<Atom>> {
+    Ok(get_u32s(conn, win, property, ATOM)
+        .await?
+        .into_iter()
+        .map(Atom::const_from_xid)
+        .collect())
+}
+
+/// Replaces a property with a list of 32 bit values of the given type.
+async fn set_u32s<Dpy: AsyncDisplay + ?Sized>(
+    conn: &mut Dpy,
+    win: Window,
+    property: Atom,
+    ty: PropertyType,
+    values: &[u32],
+) -> breadx::Result<()> {
+    win.change_property_async(
+        conn,
+        property,
+        ty,
+        PropertyFormat::ThirtyTwo,
+        PropMode::Replace,
+        values,
+    )
+    .await
+}
+
+/// Replaces a property with a list of atoms.
+pub async fn set_atoms<Dpy: AsyncDisplay + ?Sized>(
+    conn: &mut Dpy,
+    win: Window,
+    property: Atom,
+    atoms: &[Atom],
+) -> breadx::Result<()> {
+    let xids: Vec<u32> = atoms.iter().map(|atom| atom.xid).collect();
+
+    set_u32s(conn, win, property, PropertyType::Atom, &xids).await
+}
+
+/// Replaces a property with a list of windows.
+pub async fn set_windows<Dpy: AsyncDisplay + ?Sized>(
+    conn: &mut Dpy,
+    win: Window,
+    property: Atom,
+    windows: &[Window],
+) -> breadx::Result<()> {
+    let xids: Vec<u32> = windows.iter().map(|win| win.xid).collect();
+
+    set_u32s(conn, win, property, PropertyType::Window, &xids).await
+}
+
+/// Replaces a property with a list of numbers.
+pub async fn set_cardinals<Dpy: AsyncDisplay + ?Sized>(
+    conn: &mut Dpy,
+    win: Window,
+    property: Atom,
+    values: &[u32],
+) -> breadx::Result<()> {
+    set_u32s(conn, win, property, PropertyType::Cardinal, values).await
+}
+
+/// Replaces a property with a list of null terminated `UTF8_STRING`s.
+pub async fn set_strings<Dpy: AsyncDisplay + ?Sized>(
+    conn: &mut Dpy,
+    atoms: &Atoms,
+    win: Window,
+    property: Atom,
+    strings: &[&str],
+) -> Result<()> {
+    let mut bytes = Vec::new();
+
+    for string in strings {
+        bytes.extend_from_slice(string.as_bytes());
+        bytes.push(0);
+    }
+
+    // `change_property_async` only knows about the predefined types, which this isn't one of
+    conn.exchange_request_async(ChangePropertyRequest {
+        mode: PropMode::Replace,
+        window: win,
+        property,
+        ty: atoms.utf8_string,
+        format: 8,
+        data_len: u32::try_from(bytes.len()).unwrap_or(u32::MAX),
+        data: bytes.into(),
+        ..Default::default()
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Reads the `WM_HINTS` of a window, which are the defaults if it isn't set.
+pub async fn get_wm_hints<Dpy: AsyncDisplay + ?Sized>(
+    conn: &mut Dpy,
+    win: Window,
+) -> Result<WmHints> {
+    Ok(WmHints::parse(
+        &get_u32s(conn, win, WM_HINTS, WM_HINTS).await?,
+    ))
+}
+
+/// Reads the `WM_WINDOW_ROLE` of a window, which is empty if it isn't set.
+pub async fn get_role<Dpy: AsyncDisplay + ?Sized>(
+    conn: &mut Dpy,
+    atoms: &Atoms,
+    win: Window,
+) -> Result<String> {
+    Ok(get_property(conn, win, atoms.wm_window_role, None)
+        .await?
+        .map(|bytes| {
+            String::from_utf8_lossy(&bytes)
+                .trim_end_matches('\0')
+                .to_string()
+        })
+        .unwrap_or_default())
+}
+
+/// Reads the `WM_PROTOCOLS` of a window, the messages it understands from us.
+pub async fn get_protocols<Dpy: AsyncDisplay + ?Sized>(
+    conn: &mut Dpy,
+    atoms: &Atoms,
+    win: Window,
+) -> Result<Vec<Atom>> {
+    get_atoms(conn, win, atoms.wm_protocols).await
+}
+
+/// Reads the name of the machine the client owning a window runs on, if it says.
+pub async fn get_client_machine<Dpy: AsyncDisplay + ?Sized>(
+    conn: &mut Dpy,
+    win: Window,
+) -> Result<Option<String>> {
+    Ok(get_property(conn, win, WM_CLIENT_MACHINE, None)
+        .await?
+        .map(|bytes| {
+            String::from_utf8_lossy(&bytes)
+                .trim_end_matches('\0')
+                .to_string()
+        }))
+}
+
+/// Reads the `_NET_WM_PID` of a window, the process that owns it on its machine.
+pub async fn get_pid<Dpy: AsyncDisplay + ?Sized>(
+    conn: &mut Dpy,
+    atoms: &Atoms,
+    win: Window,
+) -> Result<Option<u32>> {
+    Ok(get_u32s(conn, win, atoms.net_wm_pid, CARDINAL)
+        .await?
+        .first()
+        .copied()
+        .filter(|&pid| pid != 0))
+}
+
+/// The states from the ICCCM that a client can be in, which it finds out about from `WM_STATE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WmState {
+    /// Not managed by us (anymore).
+    Withdrawn = 0,
+    /// On screen.
+    Normal = 1,
+    /// Managed, but hidden away on another workspace or in the scratchpad.
+    Iconic = 3,
+}
+
+/// Sets the `WM_STATE` of a window. Since this is done to windows that may have been destroyed
+/// already, the error is left for the caller to ignore.
+pub async fn set_wm_state<Dpy: AsyncDisplay + ?Sized>(
+    conn: &mut Dpy,
+    atoms: &Atoms,
+    win: Window,
+    state: WmState,
+) -> breadx::Result<()> {
+    // the second value is the icon window, which we don't have
+    let bytes: Vec<u8> = [state as u32, 0]
+        .iter()
+        .flat_map(|value| value.to_ne_bytes())
+        .collect();
+
+    // `WM_STATE` is its own type, which `change_property_async` doesn't know about
+    conn.exchange_request_async(ChangePropertyRequest {
+        mode: PropMode::Replace,
+        window: win,
+        property: atoms.wm_state,
+        ty: atoms.wm_state,
+        format: 32,
+        data_len: 2,
+        data: bytes.into(),
+        ..Default::default()
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Reads the `_NET_WM_WINDOW_TYPE` of a window. This is a list, most specific type first.
+pub async fn get_window_types<Dpy: AsyncDisplay + ?Sized>(
+    conn: &mut Dpy,
+    atoms: &Atoms,
+    win: Window,
+) -> Result<Vec<Atom>> {
+    get_atoms(conn, win, atoms.net_wm_window_type).await
+}
+
+/// Reads the window that `win` is a transient (e.g. a dialog) for, if any.
+pub async fn get_transient_for<Dpy: AsyncDisplay + ?Sized>(
+    conn: &mut Dpy,
+    win: Window,
+) -> Result<Option<Window>> {
+    Ok(get_u32s(conn, win, WM_TRANSIENT_FOR, WINDOW)
+        .await?
+        .first()
+        .filter(|&&xid| xid != 0)
+        .map(|&xid| Window::const_from_xid(xid)))
+}
+
+/// Reads the `WM_NORMAL_HINTS` of a window, which are all empty if it isn't set.
+pub async fn get_size_hints<Dpy: AsyncDisplay + ?Sized>(
+    conn: &mut Dpy,
+    win: Window,
+) -> Result<SizeHints> {
+    let values = get_u32s(conn, win, WM_NORMAL_HINTS, WM_SIZE_HINTS).await?;
+
+    Ok(SizeHints::parse(&values))
+}
+
+/// Reads the space a dock wants to keep for itself, preferring `_NET_WM_STRUT_PARTIAL` over the
+/// older `_NET_WM_STRUT`. This is empty if it has neither.
+pub async fn get_strut<Dpy: AsyncDisplay + ?Sized>(
+    conn: &mut Dpy,
+    atoms: &Atoms,
+    win: Window,
+) -> Result<Strut> {
+    let mut values = get_u32s(conn, win, atoms.net_wm_strut_partial, CARDINAL).await?;
+
+    if values.is_empty() {
+        values = get_u32s(conn, win, atoms.net_wm_strut, CARDINAL).await?;
+    }
+
+    Ok(Strut::parse(&values))
+}
@@ -0,0 +1,216 @@
+// Copyright (C) 2022 Infoshock Tech
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! The `[[rules]]` from the config, which pick out windows by their class, title and so on, and
+//! change what we do with them on top of what their window type says.
+
+use regex::Regex;
+use serde::Deserialize;
+
+use super::{
+    metadata::Metadata,
+    policy::{Manage, Policy, WindowType},
+    tree::Direction,
+};
+
+/// A regex for titles. It matches anywhere in the title, unless it's anchored with `^` and `$`.
+#[derive(Debug, Clone)]
+pub struct Pattern(pub Regex);
+
+/// One `[[rules]]` table. Every criterion it has must match for it to apply, so a rule without
+/// any applies to every window.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Rule {
+    /// The first half of `WM_CLASS`, ignoring case.
+    instance: Option<String>,
+    /// The second half of `WM_CLASS`, ignoring case.
+    class: Option<String>,
+    title: Option<Pattern>,
+    /// `WM_WINDOW_ROLE`, exactly.
+    role: Option<String>,
+    window_type: Option<WindowType>,
+    /// Whether the rule is checked again whenever the title changes, for applications that only
+    /// say what they are showing once they're up. A window is still only sent to a `workspace`
+    /// the first time a rule says so.
+    #[serde(default)]
+    on_title_change: bool,
+    #[serde(flatten)]
+    actions: Actions,
+}
+
+impl Rule {
+    /// Whether this rule is for a window of type `ty` with `metadata`.
+    fn matches(&self, metadata: &Metadata, ty: WindowType) -> bool {
+        let same = |wanted: &Option<String>, actual: &str| {
+            wanted
+                .as_ref()
+                .map_or(true, |wanted| wanted.eq_ignore_ascii_case(actual))
+        };
+
+        same(&self.instance, &metadata.instance)
+            && same(&self.class, &metadata.class)
+            && self
+                .title
+                .as_ref()
+                .map_or(true, |Pattern(title)| title.is_match(&metadata.title))
+            && self
+                .role
+                .as_ref()
+                .map_or(true, |role| *role == metadata.role)
+            && self.window_type.map_or(true, |wanted| wanted == ty)
+    }
+}
+
+/// What rules do to the windows they match. Anything left out stays the way it would be.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct Actions {
+    /// The name of the workspace the window goes on, instead of the current one.
+    pub workspace: Option<String>,
+    /// Whether the window floats, or is tiled, whatever its type.
+    pub float: Option<bool>,
+    pub fullscreen: Option<bool>,
+    pub border_size: Option<u16>,
+    /// The colour of the border while the window isn't focused.
+    pub border_color: Option<u32>,
+    /// Which side of the focused window a tiled window goes on.
+    pub split: Option<Direction>,
+    /// Whether we frame the window at all. Windows we don't are mapped as they are.
+    pub manage: Option<bool>,
+}
+
+impl Actions {
+    /// Everything the `rules` that match a window of type `ty` with `metadata` do, with later
+    /// rules winning. When the window has just been `retitled`, only the rules that are checked
+    /// again on title changes count.
+    pub fn of(rules: &[Rule], metadata: &Metadata, ty: WindowType, retitled: bool) -> Self {
+        rules
+            .iter()
+            .filter(|rule| !retitled || rule.on_title_change)
+            .filter(|rule| rule.matches(metadata, ty))
+            .fold(Self::default(), |actions, rule| actions.with(&rule.actions))
+    }
+
+    /// These actions, with what `other` does replacing them.
+    fn with(self, other: &Self) -> Self {
+        Self {
+            workspace: other.workspace.clone().or(self.workspace),
+            float: other.float.or(self.float),
+            fullscreen: other.fullscreen.or(self.fullscreen),
+            border_size: other.border_size.or(self.border_size),
+            border_color: other.border_color.or(self.border_color),
+            split: other.split.or(self.split),
+            manage: other.manage.or(self.manage),
+        }
+    }
+
+    /// `policy`, with the way the window is managed changed to what these actions say. Docks
+    /// keep being docks, but everything else can be made to float, tile or be left alone.
+    pub fn adjust(&self, policy: Policy) -> Policy {
+        let manage = match (self.manage, self.float, policy.manage) {
+            (Some(false), _, _) => Manage::Ignore,
+            (_, _, Manage::Dock) => Manage::Dock,
+            (_, Some(false), _) => Manage::Tile,
+            (_, Some(true), _) | (Some(true), None, Manage::Ignore) => Manage::Float,
+            (_, None, manage) => manage,
+        };
+
+        Policy { manage, ..policy }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn name() -> impl Strategy<Value = String> {
+        "[a-zA-Z]{0,8}"
+    }
+
+    fn metadata() -> impl Strategy<Value = Metadata> {
+        (name(), name(), name(), "[a-zA-Z .*+?()]{0,20}").prop_map(
+            |(instance, class, role, title)| Metadata {
+                title,
+                instance,
+                class,
+                role,
+                ..Metadata::default()
+            },
+        )
+    }
+
+    /// A rule that matches `metadata`, on whichever of its parts `picked` says.
+    fn rule_for(metadata: &Metadata, picked: [bool; 4]) -> Rule {
+        let pick = |picked: bool, value: &str| picked.then(|| value.to_owned());
+
+        Rule {
+            instance: pick(picked[0], &metadata.instance.to_ascii_uppercase()),
+            class: pick(picked[1], &metadata.class.to_ascii_lowercase()),
+            title: picked[2].then(|| {
+                Pattern(Regex::new(&format!("^{}$", regex::escape(&metadata.title))).unwrap())
+            }),
+            role: pick(picked[3], &metadata.role),
+            ..Rule::default()
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn rules_match_the_windows_they_describe(
+            metadata in metadata(),
+            other in metadata(),
+            picked in any::<[bool; 4]>(),
+        ) {
+            let rule = rule_for(&metadata, picked);
+
+            prop_assert!(rule.matches(&metadata, WindowType::Normal));
+
+            // everything a rule asks for has to be there
+            let differs = [
+                !metadata.instance.eq_ignore_ascii_case(&other.instance),
+                !metadata.class.eq_ignore_ascii_case(&other.class),
+                metadata.title != other.title,
+                metadata.role != other.role,
+            ];
+
+            if picked.iter().zip(differs).any(|(&picked, differs)| picked && differs) {
+                prop_assert!(!rule.matches(&other, WindowType::Normal));
+            }
+        }
+
+        #[test]
+        fn later_rules_win(
+            metadata in metadata(),
+            first in name(),
+            second in proptest::option::of(name()),
+            retitled in any::<bool>(),
+        ) {
+            let rule = |workspace: Option<String>, on_title_change: bool| Rule {
+                on_title_change,
+                actions: Actions {
+                    workspace,
+                    ..Actions::default()
+                },
+                ..rule_for(&metadata, [true; 4])
+            };
+
+            let rules = [rule(Some(first.clone()), true), rule(second.clone(), false)];
+            let actions = Actions::of(&rules, &metadata, WindowType::Normal, retitled);
+
+            let expected = if retitled { Some(first) } else { second.or(Some(first)) };
+            prop_assert_eq!(actions.workspace, expected);
+        }
+    }
+}
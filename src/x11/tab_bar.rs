@@ -0,0 +1,313 @@
+// Copyright (C) 2022 Infoshock Tech
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use breadx::{
+    auto::xproto::{
+        Font, Gcontext, ImageText8Request, OpenFontRequest, QueryFontRequest, Rectangle,
+    },
+    prelude::AsyncDisplayXprotoExt,
+    AsyncDisplay, AsyncDisplayExt, ConfigureWindowParameters, EventMask, GcParameters, Window,
+};
+
+use super::tree::{Dimensions, PaneMode};
+use crate::{Result, CONFIG};
+
+/// The font tab titles are drawn with. Every X server is required to have it.
+const FONT_NAME: &str = "fixed";
+
+/// Padding between the left edge of a tab and its title.
+const TEXT_PADDING: u16 = 4;
+
+/// How a pane with a tab bar is drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TabStyle {
+    /// All of the tabs side by side in a single row.
+    Tabbed,
+    /// One row per tab.
+    Stacked,
+}
+
+impl TabStyle {
+    /// The tab bar a pane in the given mode gets, if any.
+    pub fn of(mode: PaneMode) -> Option<Self> {
+        match mode {
+            PaneMode::Split => None,
+            PaneMode::Tabbed => Some(Self::Tabbed),
+            PaneMode::Stacked => Some(Self::Stacked),
+        }
+    }
+}
+
+/// A single tab as it will be drawn.
+pub struct Tab<'a> {
+    pub title: &'a str,
+    /// Whether this tab contains the focused window.
+    pub focused: bool,
+    /// Whether this tab is the one being shown.
+    pub active: bool,
+}
+
+/// The graphics context and font metrics shared by all tab bars.
+#[derive(Debug, Clone, Copy)]
+pub struct TabPainter {
+    gc: Gcontext,
+    ascent: u16,
+    descent: u16,
+    char_width: u16,
+}
+
+impl TabPainter {
+    pub async fn new<Dpy: AsyncDisplay + ?Sized>(conn: &mut Dpy) -> Result<Self> {
+        let root = conn.default_root();
+
+        let font: Font = conn
+            .exchange_xid_async(|fid| OpenFontRequest {
+                fid,
+                name: FONT_NAME.into(),
+                ..Default::default()
+            })
+            .await?;
+
+        let metrics = conn
+            .exchange_request_async(QueryFontRequest {
+                font: font.into(),
+                ..Default::default()
+            })
+            .await?;
+
+        let gc = conn
+            .create_gc_async(
+                root,
+                GcParameters {
+                    font: Some(font),
+                    ..Default::default()
+                },
+            )
+            .await?;
+
+        Ok(Self {
+            gc,
+            ascent: metrics.font_ascent.unsigned_abs(),
+            descent: metrics.font_descent.unsigned_abs(),
+            char_width: metrics.max_bounds.character_width.unsigned_abs().max(1),
+        })
+    }
+}
+
+/// The strip of tabs shown above a tabbed or stacked pane, in a window owned by the WM.
+#[derive(Debug, Clone, Copy)]
+pub struct TabBar {
+    pub window: Window,
+    pub dimensions: Dimensions,
+    pub style: TabStyle,
+}
+
+impl TabBar {
+    pub async fn create<Dpy: AsyncDisplay + ?Sized>(
+        conn: &mut Dpy,
+        dimensions: Dimensions,
+        style: TabStyle,
+    ) -> Result<Self> {
+        let root = conn.default_root();
+
+        let window = conn
+            .create_simple_window_async(
+                root,
+                dimensions.x.try_into().unwrap_or(i16::MAX),
+                dimensions.y.try_into().unwrap_or(i16::MAX),
+                dimensions.width.max(1),
+                dimensions.height.max(1),
+                0,
+                0,
+                CONFIG.tab_color(),
+            )
+            .await?;
+
+        window
+            .set_event_mask_async(conn, EventMask::EXPOSURE | EventMask::BUTTON_PRESS)
+            .await?;
+
+        window.map_async(conn).await?;
+
+        Ok(Self {
+            window,
+            dimensions,
+            style,
+        })
+    }
+
+    pub async fn configure<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        conn: &mut Dpy,
+        dimensions: Dimensions,
+        style: TabStyle,
+    ) -> Result<()> {
+        self.dimensions = dimensions;
+        self.style = style;
+
+        self.window
+            .configure_async(
+                conn,
+                ConfigureWindowParameters {
+                    x: Some(dimensions.x.into()),
+                    y: Some(dimensions.y.into()),
+                    width: Some(dimensions.width.max(1).into()),
+                    height: Some(dimensions.height.max(1).into()),
+                    ..Default::default()
+                },
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn destroy<Dpy: AsyncDisplay + ?Sized>(self, conn: &mut Dpy) -> Result<()> {
+        self.window.free_async(conn).await?;
+
+        Ok(())
+    }
+
+    /// The area of the `index`th of `count` tabs, relative to the tab bar.
+    fn tab_area(&self, index: usize, count: usize) -> Rectangle {
+        let count = u16::try_from(count).unwrap_or(u16::MAX).max(1);
+        let index = u16::try_from(index).unwrap_or(u16::MAX);
+
+        match self.style {
+            TabStyle::Tabbed => {
+                let width = self.dimensions.width / count;
+                // the last tab soaks up the pixels lost to rounding
+                let extra = if index + 1 == count {
+                    self.dimensions.width % count
+                } else {
+                    0
+                };
+
+                Rectangle {
+                    x: (index * width).try_into().unwrap_or(i16::MAX),
+                    y: 0,
+                    width: width + extra,
+                    height: self.dimensions.height,
+                }
+            }
+            TabStyle::Stacked => Rectangle {
+                x: 0,
+                y: index
+                    .saturating_mul(CONFIG.tab_height())
+                    .try_into()
+                    .unwrap_or(i16::MAX),
+                width: self.dimensions.width,
+                height: CONFIG.tab_height(),
+            },
+        }
+    }
+
+    /// Which of `count` tabs is at the given position relative to the tab bar.
+    pub fn tab_at(&self, x: i16, y: i16, count: usize) -> Option<usize> {
+        (0..count).find(|&i| {
+            let area = self.tab_area(i, count);
+
+            x >= area.x
+                && i32::from(x) < i32::from(area.x) + i32::from(area.width)
+                && y >= area.y
+                && i32::from(y) < i32::from(area.y) + i32::from(area.height)
+        })
+    }
+
+    pub async fn draw<Dpy: AsyncDisplay + ?Sized>(
+        &self,
+        conn: &mut Dpy,
+        painter: &TabPainter,
+        tabs: &[Tab<'_>],
+    ) -> Result<()> {
+        for (i, tab) in tabs.iter().enumerate() {
+            let area = self.tab_area(i, tabs.len());
+
+            let background = if tab.focused {
+                CONFIG.focused_color()
+            } else if tab.active {
+                CONFIG.border_color()
+            } else {
+                CONFIG.tab_color()
+            };
+
+            painter
+                .gc
+                .change_async(
+                    conn,
+                    GcParameters {
+                        foreground: Some(background),
+                        background: Some(background),
+                        ..Default::default()
+                    },
+                )
+                .await?;
+
+            painter
+                .gc
+                .fill_rectangle_async(conn, self.window, area.clone())
+                .await?;
+
+            // only ascii will reliably exist in the font, and `ImageText8` can't take more than
+            // 255 bytes anyways
+            let max_chars = usize::from(area.width.saturating_sub(2 * TEXT_PADDING))
+                / usize::from(painter.char_width);
+            let title: String = tab
+                .title
+                .chars()
+                .map(|c| {
+                    if c.is_ascii() && !c.is_ascii_control() {
+                        c
+                    } else {
+                        '?'
+                    }
+                })
+                .take(max_chars.min(255))
+                .collect();
+
+            if title.is_empty() {
+                continue;
+            }
+
+            painter
+                .gc
+                .change_async(
+                    conn,
+                    GcParameters {
+                        foreground: Some(CONFIG.tab_text_color()),
+                        ..Default::default()
+                    },
+                )
+                .await?;
+
+            // center the text vertically
+            let text_height = painter.ascent + painter.descent;
+            let baseline = area.height.saturating_sub(text_height) / 2 + painter.ascent;
+
+            conn.exchange_request_async(ImageText8Request {
+                drawable: self.window.into(),
+                gc: painter.gc,
+                x: area.x.saturating_add_unsigned(TEXT_PADDING),
+                y: area
+                    .y
+                    .saturating_add(baseline.try_into().unwrap_or(i16::MAX)),
+                string: title.into(),
+                ..Default::default()
+            })
+            .await?;
+        }
+
+        Ok(())
+    }
+}
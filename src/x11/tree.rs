@@ -0,0 +1,2215 @@
+// Copyright (C) 2022 Infoshock Tech
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! The tiling tree, kept apart from anything to do with X. Changing the tree and laying it out
+//! only works out where everything should go, and it's up to `client` to actually move the
+//! windows there.
+
+use slotmap::{new_key_type, SlotMap};
+use std::{
+    ops::{Index, IndexMut},
+    str::FromStr,
+};
+
+use super::layout::Layout;
+use crate::{XcrabError, CONFIG};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    /// The directionality of a pane whose children are laid out along this direction.
+    pub fn directionality(self) -> Directionality {
+        match self {
+            Self::Up | Self::Down => Directionality::Vertical,
+            Self::Left | Self::Right => Directionality::Horizontal,
+        }
+    }
+
+    /// Whether this direction points towards the end of a pane's children.
+    pub fn is_forward(self) -> bool {
+        matches!(self, Self::Down | Self::Right)
+    }
+}
+
+impl FromStr for Direction {
+    type Err = XcrabError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "up" => Ok(Self::Up),
+            "down" => Ok(Self::Down),
+            "left" => Ok(Self::Left),
+            "right" => Ok(Self::Right),
+            _ => Err(format!("Unknown direction: {s}").into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Directionality {
+    Horizontal,
+    Vertical,
+}
+
+/// How a pane shows its children.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaneMode {
+    /// Side by side, according to the pane's `Directionality`.
+    Split,
+    /// One child at a time, with a row of tabs above it.
+    Tabbed,
+    /// One child at a time, with a column of tabs above it.
+    Stacked,
+}
+
+impl FromStr for PaneMode {
+    type Err = XcrabError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "split" => Ok(Self::Split),
+            "tabbed" => Ok(Self::Tabbed),
+            "stacked" => Ok(Self::Stacked),
+            _ => Err(format!("Unknown pane layout: {s}").into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Dimensions {
+    pub(super) x: u16,
+    pub(super) y: u16,
+    pub(super) width: u16,
+    pub(super) height: u16,
+}
+
+#[cfg(test)]
+impl Dimensions {
+    /// Dimensions with both coordinates from `position` and both lengths from `size`, for the
+    /// tests of everything that works with areas of the screen.
+    pub fn strategy(
+        position: impl proptest::strategy::Strategy<Value = u16> + Clone,
+        size: impl proptest::strategy::Strategy<Value = u16> + Clone,
+    ) -> impl proptest::strategy::Strategy<Value = Self> {
+        use proptest::strategy::Strategy;
+
+        (position.clone(), position, size.clone(), size).prop_map(|(x, y, width, height)| Self {
+            x,
+            y,
+            width,
+            height,
+        })
+    }
+}
+
+impl Dimensions {
+    /// If `other` lies entirely in `direction` from `self`, returns how far away it is along
+    /// `direction` and how far it is offset perpendicular to `direction` (0 if they overlap).
+    pub fn distance_to(self, other: Self, direction: Direction) -> Option<(u32, u32)> {
+        let (x, y) = (i32::from(self.x), i32::from(self.y));
+        let (right, bottom) = (x + i32::from(self.width), y + i32::from(self.height));
+        let (other_x, other_y) = (i32::from(other.x), i32::from(other.y));
+        let (other_right, other_bottom) = (
+            other_x + i32::from(other.width),
+            other_y + i32::from(other.height),
+        );
+
+        let (primary, range, other_range) = match direction {
+            Direction::Left => (x - other_right, (y, bottom), (other_y, other_bottom)),
+            Direction::Right => (other_x - right, (y, bottom), (other_y, other_bottom)),
+            Direction::Up => (y - other_bottom, (x, right), (other_x, other_right)),
+            Direction::Down => (other_y - bottom, (x, right), (other_x, other_right)),
+        };
+
+        if primary < 0 {
+            return None;
+        }
+
+        let secondary = (other_range.0 - range.1)
+            .max(range.0 - other_range.1)
+            .max(0);
+
+        Some((primary.unsigned_abs(), secondary.unsigned_abs()))
+    }
+
+    /// Out of `candidates`, the one nearest to these dimensions in `direction`. Ones that line
+    /// up with us win over closer ones that don't, and ties go to the smallest `id`.
+    pub fn nearest<T: Ord + Copy>(
+        self,
+        candidates: impl IntoIterator<Item = (T, Self)>,
+        direction: Direction,
+    ) -> Option<T> {
+        candidates
+            .into_iter()
+            .filter_map(|(id, other)| Some((id, self.distance_to(other, direction)?)))
+            .min_by_key(|&(id, (primary, secondary))| (secondary > 0, primary, secondary, id))
+            .map(|(id, _)| id)
+    }
+
+    /// Splits these dimensions into one piece per weight along `direction`, each sized in
+    /// proportion to its weight, with `gap_size` pixels between them. If there isn't even room
+    /// for the gaps they are left out.
+    pub fn split(self, direction: Directionality, weights: &[f64], gap_size: u16) -> Vec<Self> {
+        let minimums = vec![0; weights.len()];
+
+        self.split_min(direction, weights, &minimums, gap_size)
+            .or_else(|| self.split_min(direction, weights, &minimums, 0))
+            .unwrap_or_default()
+    }
+
+    /// Like `split`, but every piece is at least as long as its entry in `minimums`. Returns
+    /// `None` if they don't all fit along with the gaps.
+    pub fn split_min(
+        self,
+        direction: Directionality,
+        weights: &[f64],
+        minimums: &[u16],
+        gap_size: u16,
+    ) -> Option<Vec<Self>> {
+        let (start, length) = match direction {
+            Directionality::Horizontal => (self.x, self.width),
+            Directionality::Vertical => (self.y, self.height),
+        };
+
+        let gaps = u16::try_from(weights.len().saturating_sub(1))
+            .ok()
+            .and_then(|count| gap_size.checked_mul(count))?;
+
+        let sizes = distribute(length.checked_sub(gaps)?, weights, minimums)?;
+
+        let mut position = start;
+
+        let pieces = sizes
+            .into_iter()
+            .map(|size| {
+                let dimensions = match direction {
+                    Directionality::Horizontal => Dimensions {
+                        x: position,
+                        width: size,
+                        ..self
+                    },
+                    Directionality::Vertical => Dimensions {
+                        y: position,
+                        height: size,
+                        ..self
+                    },
+                };
+
+                position = position.saturating_add(size).saturating_add(gap_size);
+
+                dimensions
+            })
+            .collect();
+
+        Some(pieces)
+    }
+
+    /// Splits off `height` pixels from the top, returning the top part and the rest.
+    pub fn split_top(self, height: u16) -> (Self, Self) {
+        let height = height.min(self.height);
+
+        (
+            Dimensions { height, ..self },
+            Dimensions {
+                y: self.y.saturating_add(height),
+                height: self.height - height,
+                ..self
+            },
+        )
+    }
+
+    /// Dimensions of the given size centered over these ones.
+    pub fn center(self, width: u16, height: u16) -> Self {
+        Dimensions {
+            x: self.x.saturating_add(self.width.saturating_sub(width) / 2),
+            y: self
+                .y
+                .saturating_add(self.height.saturating_sub(height) / 2),
+            width,
+            height,
+        }
+    }
+
+    /// Whether the point (`x`, `y`) is inside these dimensions.
+    pub fn contains(self, x: u16, y: u16) -> bool {
+        (self.x..self.x.saturating_add(self.width)).contains(&x)
+            && (self.y..self.y.saturating_add(self.height)).contains(&y)
+    }
+
+    /// The length of these dimensions along `directionality`.
+    pub fn length(self, directionality: Directionality) -> u16 {
+        match directionality {
+            Directionality::Horizontal => self.width,
+            Directionality::Vertical => self.height,
+        }
+    }
+}
+
+/// Shares out `amount` pixels in proportion to `weights`, but with each share at least its entry
+/// in `minimums`. Returns `None` if the minimums add up to more than `amount`.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn distribute(amount: u16, weights: &[f64], minimums: &[u16]) -> Option<Vec<u16>> {
+    if minimums.iter().map(|&min| u32::from(min)).sum::<u32>() > u32::from(amount) {
+        return None;
+    }
+
+    // whoever would get less than their minimum gets exactly that, and everyone else shares
+    // what's left, which can push someone else under theirs
+    let mut pinned = vec![false; weights.len()];
+
+    let shares = loop {
+        let free_amount = f64::from(amount)
+            - minimums
+                .iter()
+                .zip(&pinned)
+                .filter(|&(_, &pinned)| pinned)
+                .map(|(&min, _)| f64::from(min))
+                .sum::<f64>();
+        let free_weight: f64 = weights
+            .iter()
+            .zip(&pinned)
+            .filter(|&(_, &pinned)| !pinned)
+            .map(|(weight, _)| weight)
+            .sum();
+
+        let shares: Vec<f64> = weights
+            .iter()
+            .zip(minimums)
+            .zip(&pinned)
+            .map(|((&weight, &min), &pinned)| {
+                if pinned || free_weight <= 0.0 {
+                    f64::from(min)
+                } else {
+                    free_amount * weight / free_weight
+                }
+            })
+            .collect();
+
+        let mut changed = false;
+
+        for ((share, &min), pinned) in shares.iter().zip(minimums).zip(&mut pinned) {
+            if !*pinned && *share < f64::from(min) {
+                *pinned = true;
+                changed = true;
+            }
+        }
+
+        if !changed {
+            break shares;
+        }
+    };
+
+    let mut sizes: Vec<u16> = shares.iter().map(|share| share.max(0.0) as u16).collect();
+
+    // hand out the pixels lost to rounding, starting with whoever lost the most
+    let mut by_remainder: Vec<usize> = (0..sizes.len()).collect();
+    by_remainder.sort_by(|&a, &b| {
+        let remainder = |i: usize| shares[i] - f64::from(sizes[i]);
+        remainder(b).total_cmp(&remainder(a))
+    });
+
+    let total: u32 = sizes.iter().map(|&size| u32::from(size)).sum();
+    let excess = u32::from(amount).saturating_sub(total);
+
+    for &i in by_remainder.iter().cycle().take(excess as usize) {
+        sizes[i] += 1;
+    }
+
+    Some(sizes)
+}
+
+/// How much to grow a client by in `resize`.
+#[derive(Debug, Clone, Copy)]
+pub enum ResizeAmount {
+    Pixels(i32),
+    /// Percent of the space available to the pane being resized.
+    Percent(i32),
+}
+
+impl FromStr for ResizeAmount {
+    type Err = XcrabError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let parse = |n: &str| {
+            n.parse::<i32>()
+                .map_err(|_| XcrabError::from(format!("Invalid resize amount: {s}")))
+        };
+
+        if let Some(n) = s.strip_suffix('%').or_else(|| s.strip_suffix("ppt")) {
+            Ok(Self::Percent(parse(n)?))
+        } else {
+            Ok(Self::Pixels(parse(s.strip_suffix("px").unwrap_or(s))?))
+        }
+    }
+}
+
+/// The smallest share of a pane a single child can be resized down to.
+const MIN_WEIGHT_FRACTION: f64 = 0.05;
+
+/// The space left between and above things when laying out the tree.
+#[derive(Debug, Clone, Copy)]
+pub struct Spacing {
+    /// The gap between the children of a split pane.
+    pub gap_size: u16,
+    /// The height of a single row of tabs.
+    pub tab_height: u16,
+    /// The smallest width and height a client is given before its pane is tabbed instead.
+    pub min_size: u16,
+}
+
+impl Spacing {
+    pub fn from_config() -> Self {
+        Self {
+            gap_size: CONFIG.gap_size(),
+            tab_height: CONFIG.tab_height(),
+            min_size: CONFIG.min_client_size(),
+        }
+    }
+
+    /// The height of the tab bar of a pane in the given mode with `count` children, or `None` if
+    /// it doesn't have one.
+    pub fn tab_bar_height(self, mode: PaneMode, count: usize) -> Option<u16> {
+        let rows = match mode {
+            PaneMode::Split => return None,
+            PaneMode::Tabbed => 1,
+            PaneMode::Stacked => u16::try_from(count).unwrap_or(u16::MAX).max(1),
+        };
+
+        Some(self.tab_height.saturating_mul(rows))
+    }
+}
+
+/// Where things should go after laying out (part of) the tree.
+#[derive(Debug, Clone, Default)]
+pub struct Placements {
+    /// Where each client goes.
+    pub clients: Vec<(XcrabKey, Dimensions)>,
+    /// Where the tab bar of each tabbed or stacked pane goes.
+    pub tab_bars: Vec<(XcrabKey, Dimensions)>,
+    /// Rectangles to raise above the others, in order, since some of them overlap. For clients
+    /// this is their frame, and for tabbed or stacked panes their tab bar.
+    pub raise: Vec<XcrabKey>,
+}
+
+new_key_type!(
+    pub struct XcrabKey;
+);
+
+#[derive(Debug, Clone)]
+pub struct Rectangle<T> {
+    pub parent: XcrabKey,
+    pub cached_dimensions: Dimensions,
+    pub contents: RectangleContents<T>,
+}
+
+impl<T> Rectangle<T> {
+    pub fn unwrap_pane(&self) -> &Pane {
+        match &self.contents {
+            RectangleContents::Pane(pane) => pane,
+            RectangleContents::Client(_) => unreachable!(),
+        }
+    }
+
+    pub fn unwrap_client(&self) -> &T {
+        match &self.contents {
+            RectangleContents::Pane(_) => unreachable!(),
+            RectangleContents::Client(client) => client,
+        }
+    }
+
+    pub fn unwrap_pane_mut(&mut self) -> &mut Pane {
+        match &mut self.contents {
+            RectangleContents::Pane(pane) => pane,
+            RectangleContents::Client(_) => unreachable!(),
+        }
+    }
+
+    pub fn unwrap_client_mut(&mut self) -> &mut T {
+        match &mut self.contents {
+            RectangleContents::Pane(_) => unreachable!(),
+            RectangleContents::Client(client) => client,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum RectangleContents<T> {
+    Pane(Pane),
+    Client(T),
+}
+
+#[derive(Debug, Clone)]
+pub struct Pane {
+    pub children: Vec<XcrabKey>,
+    /// How much space each child gets relative to its siblings. Always the same length as
+    /// `children`, so go through the methods below instead of modifying `children` directly.
+    weights: Vec<f64>,
+    pub directionality: Directionality,
+    pub mode: PaneMode,
+    /// The child that most recently contained the focused window. This is the one that is shown
+    /// when the pane is tabbed or stacked.
+    active: Option<XcrabKey>,
+    /// Whether the pane was too small to show its children side by side the last time it was
+    /// laid out, in which case it is tabbed until it gets more room.
+    squashed: bool,
+}
+
+impl Pane {
+    fn new(children: Vec<XcrabKey>, directionality: Directionality) -> Self {
+        Self {
+            weights: vec![1.0; children.len()],
+            children,
+            directionality,
+            mode: PaneMode::Split,
+            active: None,
+            squashed: false,
+        }
+    }
+
+    /// How the pane is shown, which is tabbed for split panes that got squashed.
+    pub fn shown_mode(&self) -> PaneMode {
+        if self.squashed && self.mode == PaneMode::Split {
+            PaneMode::Tabbed
+        } else {
+            self.mode
+        }
+    }
+
+    /// The child to show when tabbed or stacked.
+    pub fn active_child(&self) -> Option<XcrabKey> {
+        self.active
+            .filter(|active| self.children.contains(active))
+            .or_else(|| self.children.first().copied())
+    }
+
+    /// Inserts `key` at `index`. It is given the average weight of its new siblings, so it takes
+    /// an even share of the pane without disturbing how the others were sized against each other.
+    #[allow(clippy::cast_precision_loss)]
+    fn insert(&mut self, index: usize, key: XcrabKey) {
+        let weight = if self.weights.is_empty() {
+            1.0
+        } else {
+            self.weights.iter().sum::<f64>() / self.weights.len() as f64
+        };
+
+        self.children.insert(index, key);
+        self.weights.insert(index, weight);
+    }
+
+    /// Removes `key` from the children, if it is one.
+    fn remove(&mut self, key: XcrabKey) {
+        if let Some(index) = self.children.iter().position(|&v| v == key) {
+            self.children.remove(index);
+            self.weights.remove(index);
+        }
+    }
+
+    /// Swaps two children along with their weights.
+    fn swap(&mut self, a: usize, b: usize) {
+        self.children.swap(a, b);
+        self.weights.swap(a, b);
+    }
+
+    /// Gives every child an equal share again.
+    fn balance(&mut self) {
+        self.weights.fill(1.0);
+    }
+
+    /// The index of `key` in `children`. Panics if `key` isn't a child of this pane.
+    fn position(&self, key: XcrabKey) -> usize {
+        self.children.iter().position(|&v| v == key).unwrap()
+    }
+}
+
+/// A position in the tree that a client can be put back into.
+#[derive(Debug, Clone, Copy)]
+pub struct TreeSlot {
+    parent: XcrabKey,
+    index: usize,
+    directionality: Directionality,
+    /// A neighbour of the client and whether the client came after it, for when `parent` has
+    /// been tidied away in the meantime.
+    sibling: Option<(XcrabKey, bool)>,
+}
+
+/// The rectangles of every tree, one per workspace. The root of a tree is its own parent, and
+/// whoever owns a tree keeps track of its root, which the methods that can replace it take a
+/// `&mut` to.
+#[derive(Debug, Clone)]
+pub struct Tree<T> {
+    rects: SlotMap<XcrabKey, Rectangle<T>>,
+}
+
+impl<T> Default for Tree<T> {
+    fn default() -> Self {
+        Self {
+            rects: SlotMap::with_key(),
+        }
+    }
+}
+
+impl<T> Index<XcrabKey> for Tree<T> {
+    type Output = Rectangle<T>;
+
+    fn index(&self, key: XcrabKey) -> &Rectangle<T> {
+        &self.rects[key]
+    }
+}
+
+impl<T> IndexMut<XcrabKey> for Tree<T> {
+    fn index_mut(&mut self, key: XcrabKey) -> &mut Rectangle<T> {
+        &mut self.rects[key]
+    }
+}
+
+impl<T> Tree<T> {
+    pub fn get(&self, key: XcrabKey) -> Option<&Rectangle<T>> {
+        self.rects.get(key)
+    }
+
+    pub fn get_mut(&mut self, key: XcrabKey) -> Option<&mut Rectangle<T>> {
+        self.rects.get_mut(key)
+    }
+
+    /// Starts a new tree covering `area` with `client` as its only rectangle, and returns its
+    /// key, which is the new root.
+    pub fn insert_root(&mut self, client: T, area: Dimensions) -> XcrabKey {
+        self.rects.insert_with_key(|key| Rectangle {
+            parent: key,
+            cached_dimensions: area,
+            contents: RectangleContents::Client(client),
+        })
+    }
+
+    /// Given the `rect_key` from a `parent -> rect` relationship, makes A
+    /// `parent -> new_pane -> rect` relationship, then returns `new_pane_key`
+    pub fn insert_pane_above(
+        &mut self,
+        rect_key: XcrabKey,
+        directionality: Directionality,
+        root: &mut Option<XcrabKey>,
+    ) -> XcrabKey {
+        let rect = &self.rects[rect_key];
+        let rect_dimensions = rect.cached_dimensions;
+        let parent_key = rect.parent;
+
+        let new_pane = Rectangle {
+            parent: parent_key,
+            cached_dimensions: rect_dimensions,
+            contents: RectangleContents::Pane(Pane::new(vec![rect_key], directionality)),
+        };
+
+        let new_pane_key = if parent_key == rect_key {
+            // the given node was the root node
+
+            // this new pane will be the new root, so it becomes its own parent
+            let new_pane_key = self.rects.insert_with_key(|key| Rectangle {
+                parent: key,
+                ..new_pane
+            });
+
+            *root = Some(new_pane_key);
+
+            new_pane_key
+        } else {
+            // the given node was not the root node, and thus has a parent
+
+            let new_pane_key = self.rects.insert(new_pane);
+
+            let parent_pane = self.rects[parent_key].unwrap_pane_mut();
+            let index = parent_pane.position(rect_key);
+            // replace the "parent -> rect" relationship with a "parent -> new_pane" relationship
+            parent_pane.children[index] = new_pane_key;
+
+            if parent_pane.active == Some(rect_key) {
+                parent_pane.active = Some(new_pane_key);
+            }
+
+            new_pane_key
+        };
+
+        self.rects[rect_key].parent = new_pane_key;
+
+        new_pane_key
+    }
+
+    /// Adds `client` to the pane at `parent_key` at `index`, and returns its key.
+    fn insert_child(&mut self, parent_key: XcrabKey, index: usize, client: T) -> XcrabKey {
+        let key = self.rects.insert(Rectangle {
+            parent: parent_key,
+            // this will get overriden once the parent is laid out
+            cached_dimensions: Dimensions::default(),
+            contents: RectangleContents::Client(client),
+        });
+
+        self.rects[parent_key].unwrap_pane_mut().insert(index, key);
+
+        key
+    }
+
+    /// Puts `client` next to `anchor` in the given direction, in the nearest pane above `anchor`
+    /// that goes the right way (wrapping the whole tree in a new one if there is none), and
+    /// returns its key.
+    pub fn insert_near(
+        &mut self,
+        anchor: XcrabKey,
+        client: T,
+        direction: Direction,
+        root: &mut Option<XcrabKey>,
+    ) -> XcrabKey {
+        let target_directionality = direction.directionality();
+
+        // this var will be used in the upcoming loop
+        let mut child_key = anchor;
+
+        // go up the chain (using `Rectangle.parent`) until you find a pane with the correct directionality
+        let parent_key = loop {
+            let parent_key = self.rects[child_key].parent;
+
+            if parent_key == child_key {
+                // uh oh, we hit the top, now we will wrap the root
+                // in a new pane and make this new pane the root
+                break self.insert_pane_above(child_key, target_directionality, root);
+            }
+
+            if self.rects[parent_key].unwrap_pane().directionality == target_directionality {
+                // yay! found it
+                break parent_key;
+            }
+
+            // nope, continue
+            child_key = parent_key;
+        };
+
+        // `parent_key` now holds the key for the pane with the target
+        // directionality, and `child_key` holds the child key which will
+        // be used to find where to insert our new client
+        let index = self.rects[parent_key].unwrap_pane().position(child_key)
+            + usize::from(direction.is_forward());
+
+        self.insert_child(parent_key, index, client)
+    }
+
+    /// Puts `client` back into the tree rooted at `root` at `slot`, and returns its key. Returns
+    /// `None` if neither the pane it was in nor its neighbour are still in that tree.
+    pub fn insert_at_slot(
+        &mut self,
+        client: T,
+        slot: TreeSlot,
+        root: &mut Option<XcrabKey>,
+    ) -> Option<XcrabKey> {
+        let in_tree = |key| self.rects.contains_key(key) && Some(self.root_of(key)) == *root;
+
+        let (parent_key, index) = if let Some(RectangleContents::Pane(pane)) =
+            in_tree(slot.parent).then(|| &self.rects[slot.parent].contents)
+        {
+            (slot.parent, slot.index.min(pane.children.len()))
+        } else {
+            // the pane got tidied away after we left, so go back next to our neighbour instead
+            let (sibling, after) = slot.sibling.filter(|&(sibling, _)| in_tree(sibling))?;
+
+            let mut parent_key = self.rects[sibling].parent;
+
+            if parent_key == sibling
+                || self.rects[parent_key].unwrap_pane().directionality != slot.directionality
+            {
+                parent_key = self.insert_pane_above(sibling, slot.directionality, root);
+            }
+
+            let index = self.rects[parent_key].unwrap_pane().position(sibling);
+
+            (parent_key, index + usize::from(after))
+        };
+
+        let key = self.insert_child(parent_key, index, client);
+
+        // the sibling might have been a pane going the same way as the one it got wrapped in
+        self.normalize(root);
+
+        Some(key)
+    }
+
+    /// Removes the client at `key` from the tree, tidying up after it. Returns the client and
+    /// where in the tree it was, unless it was the only client.
+    pub fn take(&mut self, key: XcrabKey, root: &mut Option<XcrabKey>) -> (T, Option<TreeSlot>) {
+        let parent_key = self.rects[key].parent;
+
+        let slot = if parent_key == key {
+            // the root client, so the tree will be empty afterwards
+            *root = None;
+
+            None
+        } else {
+            let pane = self.rects[parent_key].unwrap_pane();
+            let index = pane.position(key);
+
+            // prefer the neighbour after us, like `insert_at_slot` does with the index
+            let sibling = pane
+                .children
+                .get(index + 1)
+                .map(|&sibling| (sibling, false))
+                .or_else(|| Some((pane.children[index.checked_sub(1)?], true)));
+
+            let slot = TreeSlot {
+                parent: parent_key,
+                index,
+                directionality: pane.directionality,
+                sibling,
+            };
+
+            self.detach(key);
+
+            Some(slot)
+        };
+
+        let RectangleContents::Client(client) = self.rects.remove(key).unwrap().contents else {
+            unreachable!()
+        };
+
+        self.normalize(root);
+
+        (client, slot)
+    }
+
+    /// Moves `key` one step in the given direction. Within a pane of the right directionality it
+    /// trades places with its sibling (or enters it, if the sibling is a pane). At the edge of a
+    /// pane it escapes into the nearest ancestor pane that has the right directionality, wrapping
+    /// the root in a new pane if there is none. Returns whether anything changed.
+    pub fn move_rect(
+        &mut self,
+        key: XcrabKey,
+        direction: Direction,
+        root: &mut Option<XcrabKey>,
+    ) -> bool {
+        let target_directionality = direction.directionality();
+
+        let parent_key = self.rects[key].parent;
+
+        if parent_key == key {
+            // the only client, there is nowhere to go
+            return false;
+        }
+
+        let parent_pane = self.rects[parent_key].unwrap_pane();
+
+        if parent_pane.directionality == target_directionality {
+            let index = parent_pane.position(key);
+            let neighbour_index = if direction.is_forward() {
+                index
+                    .checked_add(1)
+                    .filter(|&i| i < parent_pane.children.len())
+            } else {
+                index.checked_sub(1)
+            };
+
+            if let Some(neighbour_index) = neighbour_index {
+                let neighbour_key = parent_pane.children[neighbour_index];
+
+                if let RectangleContents::Pane(neighbour) = &self.rects[neighbour_key].contents {
+                    // enter the pane from the side we are coming from
+                    let index = if direction.is_forward() {
+                        0
+                    } else {
+                        neighbour.children.len()
+                    };
+
+                    self.detach(key);
+                    self.attach(key, neighbour_key, index);
+
+                    // we might have left our old pane with a single child
+                    self.normalize(root);
+                } else {
+                    self.rects[parent_key]
+                        .unwrap_pane_mut()
+                        .swap(index, neighbour_index);
+                }
+
+                return true;
+            }
+        }
+
+        // we're at the edge of our pane (or it goes the wrong way), so find the nearest ancestor
+        // that goes the right way and put ourselves next to the child of it that contains us
+        let mut child_key = parent_key;
+
+        loop {
+            let ancestor_key = self.rects[child_key].parent;
+
+            if ancestor_key == child_key {
+                // we hit the root
+                if let RectangleContents::Pane(pane) = &self.rects[child_key].contents {
+                    if pane.directionality == target_directionality {
+                        // already at the very edge of the screen
+                        return false;
+                    }
+                }
+
+                let new_root_key = self.insert_pane_above(child_key, target_directionality, root);
+
+                self.detach(key);
+                // the new root's only child is the old root, so we go either before or after it
+                self.attach(key, new_root_key, usize::from(direction.is_forward()));
+
+                break;
+            }
+
+            let ancestor = self.rects[ancestor_key].unwrap_pane();
+
+            if ancestor.directionality == target_directionality {
+                let mut index = ancestor.position(child_key);
+
+                if direction.is_forward() {
+                    index += 1;
+                }
+
+                self.detach(key);
+                self.attach(key, ancestor_key, index);
+
+                break;
+            }
+
+            child_key = ancestor_key;
+        }
+
+        self.normalize(root);
+
+        true
+    }
+
+    /// Exchanges the places of two rectangles in the tree. Within one pane they take their
+    /// weights with them, like `move_rect` does, and whichever tab was active stays active. Across
+    /// panes each one takes the other's weight, so neither pane changes shape, and a pane whose
+    /// active child left makes the one that came in active instead.
+    pub fn swap(&mut self, a: XcrabKey, b: XcrabKey) {
+        let a_parent = self.rects[a].parent;
+        let b_parent = self.rects[b].parent;
+
+        if a_parent == b_parent {
+            let pane = self.rects[a_parent].unwrap_pane_mut();
+            let a_index = pane.position(a);
+            let b_index = pane.position(b);
+            pane.swap(a_index, b_index);
+        } else {
+            for (parent_key, old, new) in [(a_parent, a, b), (b_parent, b, a)] {
+                let pane = self.rects[parent_key].unwrap_pane_mut();
+                let index = pane.position(old);
+                let active = pane.active_child();
+                pane.children[index] = new;
+
+                // `active` can be a child that has since left, which mustn't come back as `new`
+                pane.active = if active == Some(old) {
+                    Some(new)
+                } else {
+                    active
+                };
+            }
+
+            self.rects[a].parent = b_parent;
+            self.rects[b].parent = a_parent;
+        }
+    }
+
+    /// Grows `key` by moving its edge in the given direction, taking the space from (or, for
+    /// negative amounts, giving it to) the sibling on that side. The nearest ancestor pane where
+    /// there actually is such a sibling is the one that gets resized, and its key is returned.
+    pub fn resize(
+        &mut self,
+        key: XcrabKey,
+        direction: Direction,
+        amount: ResizeAmount,
+        gap_size: u16,
+    ) -> Option<XcrabKey> {
+        let mut child_key = key;
+
+        // find a pane with the right directionality where we aren't at the edge
+        let (pane_key, index, neighbour_index) = loop {
+            let parent_key = self.rects[child_key].parent;
+
+            if parent_key == child_key {
+                // nothing to resize against
+                return None;
+            }
+
+            let pane = self.rects[parent_key].unwrap_pane();
+
+            if pane.directionality == direction.directionality() {
+                let index = pane.position(child_key);
+                let neighbour_index = if direction.is_forward() {
+                    index.checked_add(1).filter(|&i| i < pane.children.len())
+                } else {
+                    index.checked_sub(1)
+                };
+
+                if let Some(neighbour_index) = neighbour_index {
+                    break (parent_key, index, neighbour_index);
+                }
+            }
+
+            child_key = parent_key;
+        };
+
+        let rect = &mut self.rects[pane_key];
+        let length = rect.cached_dimensions.length(direction.directionality());
+        let pane = rect.unwrap_pane_mut();
+
+        let gaps =
+            gap_size.saturating_mul(u16::try_from(pane.children.len() - 1).unwrap_or(u16::MAX));
+        let available = f64::from(length.saturating_sub(gaps).max(1));
+
+        let pixels = match amount {
+            ResizeAmount::Pixels(pixels) => f64::from(pixels),
+            ResizeAmount::Percent(percent) => available * f64::from(percent) / 100.0,
+        };
+
+        let total_weight: f64 = pane.weights.iter().sum();
+        let min_weight = total_weight * MIN_WEIGHT_FRACTION;
+
+        // don't let either side get squashed to nothing
+        let lower = min_weight - pane.weights[index];
+        let upper = pane.weights[neighbour_index] - min_weight;
+
+        if lower > upper {
+            return None;
+        }
+
+        // a side can already be under the minimum if more children came in after it was shrunk,
+        // which mustn't turn growing into shrinking or the other way around
+        let wanted = pixels * total_weight / available;
+        let delta = if wanted >= 0.0 {
+            wanted.min(upper.max(0.0))
+        } else {
+            wanted.max(lower.min(0.0))
+        };
+
+        pane.weights[index] += delta;
+        pane.weights[neighbour_index] -= delta;
+
+        Some(pane_key)
+    }
+
+    /// Resets the sizes of every pane at or below `key` back to even splits.
+    pub fn balance(&mut self, key: XcrabKey) {
+        for key in self.subtree(key) {
+            if let RectangleContents::Pane(pane) = &mut self.rects[key].contents {
+                pane.balance();
+            }
+        }
+    }
+
+    /// Switches the pane containing `key` to the given mode, wrapping `key` in a new pane if it
+    /// doesn't have one.
+    pub fn set_mode(&mut self, key: XcrabKey, mode: PaneMode, root: &mut Option<XcrabKey>) {
+        let mut parent_key = self.rects[key].parent;
+
+        if parent_key == key {
+            parent_key = self.insert_pane_above(key, Directionality::Horizontal, root);
+        }
+
+        self.rects[parent_key].unwrap_pane_mut().mode = mode;
+        self.mark_active(key);
+
+        // a pane going back to being split might not be needed anymore
+        self.normalize(root);
+    }
+
+    /// Removes the `parent -> key` relationship. The rect itself stays in `rects` and its
+    /// `parent` field is left dangling until it gets `attach`ed somewhere else.
+    fn detach(&mut self, key: XcrabKey) {
+        let parent_key = self.rects[key].parent;
+
+        self.rects[parent_key].unwrap_pane_mut().remove(key);
+    }
+
+    /// Makes a `parent -> key` relationship, with `key` at `index` in the parent's children.
+    fn attach(&mut self, key: XcrabKey, parent_key: XcrabKey, index: usize) {
+        self.rects[parent_key].unwrap_pane_mut().insert(index, key);
+
+        self.rects[key].parent = parent_key;
+    }
+
+    /// Tidies up the tree rooted at `root` after it was changed: empty panes are removed, split
+    /// panes with a single child are replaced by that child, and split panes inside split panes
+    /// going the same way are merged into their parent. Tabbed and stacked panes are left alone
+    /// apart from removing them when empty, since they look different from their children.
+    pub fn normalize(&mut self, root: &mut Option<XcrabKey>) {
+        let Some(root_key) = *root else {
+            return;
+        };
+
+        // children before their parents, so every pane only has to look one level down
+        for key in self.subtree(root_key).into_iter().rev() {
+            self.merge_children(key);
+
+            let rect = &self.rects[key];
+            let parent_key = rect.parent;
+
+            let RectangleContents::Pane(pane) = &rect.contents else {
+                continue;
+            };
+
+            match pane.children[..] {
+                [] if parent_key == key => {
+                    self.rects.remove(key);
+                    *root = None;
+                }
+                [] => {
+                    self.detach(key);
+                    self.rects.remove(key);
+                }
+                [child] if pane.mode == PaneMode::Split => self.hoist(child, root),
+                _ => {}
+            }
+        }
+    }
+
+    /// Replaces the pane at `key` with any of its children that are split panes going the same
+    /// way as it, keeping the space each of them took up.
+    fn merge_children(&mut self, key: XcrabKey) {
+        let RectangleContents::Pane(pane) = &self.rects[key].contents else {
+            return;
+        };
+
+        if pane.mode != PaneMode::Split {
+            return;
+        }
+
+        let directionality = pane.directionality;
+        let mut index = 0;
+
+        while let Some(&child_key) = self.rects[key].unwrap_pane().children.get(index) {
+            let inner = match &self.rects[child_key].contents {
+                RectangleContents::Pane(inner)
+                    if inner.mode == PaneMode::Split && inner.directionality == directionality =>
+                {
+                    inner.clone()
+                }
+                _ => {
+                    index += 1;
+                    continue;
+                }
+            };
+
+            self.rects.remove(child_key);
+
+            for &grandchild in &inner.children {
+                self.rects[grandchild].parent = key;
+            }
+
+            let pane = self.rects[key].unwrap_pane_mut();
+
+            // the merged children share out the weight the pane they were in had
+            let weight = pane.weights[index];
+            let total: f64 = inner.weights.iter().sum();
+            let weights = inner.weights.iter().map(|w| weight * w / total);
+
+            pane.children
+                .splice(index..=index, inner.children.iter().copied());
+            pane.weights.splice(index..=index, weights);
+
+            if pane.active == Some(child_key) {
+                pane.active = inner.active_child();
+            }
+
+            index += inner.children.len();
+        }
+    }
+
+    /// Puts `key` where its parent pane was, and removes the parent. The parent should have no
+    /// other children.
+    fn hoist(&mut self, key: XcrabKey, root: &mut Option<XcrabKey>) {
+        let pane_key = self.rects[key].parent;
+        let Rectangle {
+            parent: grandparent_key,
+            cached_dimensions,
+            ..
+        } = self.rects.remove(pane_key).unwrap();
+
+        // the space is laid out again afterwards, but the root doesn't have anyone to do it
+        self.rects[key].cached_dimensions = cached_dimensions;
+
+        if grandparent_key == pane_key {
+            self.rects[key].parent = key;
+            *root = Some(key);
+        } else {
+            self.rects[key].parent = grandparent_key;
+
+            let grandparent = self.rects[grandparent_key].unwrap_pane_mut();
+            let index = grandparent.position(pane_key);
+            grandparent.children[index] = key;
+
+            if grandparent.active == Some(pane_key) {
+                grandparent.active = Some(key);
+            }
+        }
+    }
+
+    /// Lays out the rectangle at `key` and everything below it, using `dimensions` if given or
+    /// the rectangle's cached dimensions otherwise.
+    pub fn layout(
+        &mut self,
+        key: XcrabKey,
+        dimensions: Option<Dimensions>,
+        spacing: Spacing,
+    ) -> Placements {
+        let dimensions = dimensions.unwrap_or(self.rects[key].cached_dimensions);
+        let mut placements = Placements::default();
+
+        self.layout_into(key, dimensions, spacing, &mut placements);
+
+        placements
+    }
+
+    fn layout_into(
+        &mut self,
+        key: XcrabKey,
+        dimensions: Dimensions,
+        spacing: Spacing,
+        placements: &mut Placements,
+    ) {
+        self.rects[key].cached_dimensions = dimensions;
+
+        let RectangleContents::Pane(pane) = &self.rects[key].contents else {
+            placements.clients.push((key, dimensions));
+            return;
+        };
+
+        if pane.children.is_empty() {
+            return;
+        }
+
+        let children = pane.children.clone();
+
+        // a split pane that can't give every child its minimum size falls back to tabs
+        let split = if pane.mode == PaneMode::Split {
+            let minimums: Vec<u16> = children
+                .iter()
+                .map(|&child| self.min_length(child, pane.directionality, spacing))
+                .collect();
+
+            dimensions.split_min(
+                pane.directionality,
+                &pane.weights,
+                &minimums,
+                spacing.gap_size,
+            )
+        } else {
+            None
+        };
+
+        let pane = self.rects[key].unwrap_pane_mut();
+        pane.squashed = pane.mode == PaneMode::Split && split.is_none();
+
+        let (pieces, active) = if let Some(pieces) = split {
+            (pieces, None)
+        } else {
+            let height = spacing
+                .tab_bar_height(pane.shown_mode(), children.len())
+                .unwrap_or_default();
+            let (tab_bar, content) = dimensions.split_top(height);
+            placements.tab_bars.push((key, tab_bar));
+
+            // every child gets the whole content area, and the active one is raised above the
+            // others
+            (vec![content; children.len()], pane.active_child())
+        };
+
+        for (child, dimensions) in children.into_iter().zip(pieces) {
+            self.layout_into(child, dimensions, spacing, placements);
+        }
+
+        if let Some(active) = active {
+            let visible = self
+                .subtree(active)
+                .into_iter()
+                .filter(|&key| self.is_visible(key));
+
+            placements.raise.extend(visible);
+        }
+    }
+
+    /// The smallest length along `directionality` that `key` can be laid out in without any
+    /// client getting smaller than `spacing.min_size`, letting split panes fall back to tabs.
+    pub fn min_length(
+        &self,
+        key: XcrabKey,
+        directionality: Directionality,
+        spacing: Spacing,
+    ) -> u16 {
+        let RectangleContents::Pane(pane) = &self.rects[key].contents else {
+            return spacing.min_size;
+        };
+
+        let minimums = pane
+            .children
+            .iter()
+            .map(|&child| self.min_length(child, directionality, spacing));
+
+        let tabbed = |mode| {
+            let tab_bar = match directionality {
+                Directionality::Horizontal => 0,
+                Directionality::Vertical => spacing
+                    .tab_bar_height(mode, pane.children.len())
+                    .unwrap_or_default(),
+            };
+
+            pane.children
+                .iter()
+                .map(|&child| self.min_length(child, directionality, spacing))
+                .max()
+                .unwrap_or_default()
+                .saturating_add(tab_bar)
+        };
+
+        match pane.mode {
+            PaneMode::Split if pane.directionality == directionality => {
+                let gaps = u16::try_from(pane.children.len().saturating_sub(1))
+                    .unwrap_or(u16::MAX)
+                    .saturating_mul(spacing.gap_size);
+                let split = minimums.fold(gaps, u16::saturating_add);
+
+                split.min(tabbed(PaneMode::Tabbed))
+            }
+            PaneMode::Split => minimums.max().unwrap_or_default(),
+            mode => tabbed(mode),
+        }
+    }
+
+    /// Places the clients below `root` using an automatic layout, in the order they appear in the
+    /// tree. In some layouts clients overlap, so `focused` is raised above the others.
+    pub fn arrange(
+        &mut self,
+        root: XcrabKey,
+        layout: &dyn Layout,
+        spacing: Spacing,
+        focused: Option<XcrabKey>,
+    ) -> Placements {
+        let leaves = self.leaves(root);
+        let area = self.rects[root].cached_dimensions;
+        let pieces = layout.arrange(area, leaves.len(), spacing.gap_size);
+
+        let clients: Vec<_> = leaves.into_iter().zip(pieces).collect();
+
+        for &(key, dimensions) in &clients {
+            self.rects[key].cached_dimensions = dimensions;
+        }
+
+        Placements {
+            clients,
+            tab_bars: Vec::new(),
+            raise: focused.into_iter().collect(),
+        }
+    }
+
+    /// Whether `key` isn't hidden in an inactive tab of some tabbed or stacked pane.
+    pub fn is_visible(&self, mut key: XcrabKey) -> bool {
+        loop {
+            let parent_key = self.rects[key].parent;
+
+            if parent_key == key {
+                return true;
+            }
+
+            let parent = self.rects[parent_key].unwrap_pane();
+
+            if parent.shown_mode() != PaneMode::Split && parent.active_child() != Some(key) {
+                return false;
+            }
+
+            key = parent_key;
+        }
+    }
+
+    /// Marks every pane above `key` as having its focus on the child leading to `key`.
+    pub fn mark_active(&mut self, mut key: XcrabKey) {
+        loop {
+            let parent_key = self.rects[key].parent;
+
+            if parent_key == key {
+                return;
+            }
+
+            self.rects[parent_key].unwrap_pane_mut().active = Some(key);
+
+            key = parent_key;
+        }
+    }
+
+    /// The client that would get focus if `key` were focused: `key` itself if it's a client, or
+    /// otherwise its most recently focused descendant.
+    pub fn active_leaf(&self, mut key: XcrabKey) -> Option<XcrabKey> {
+        loop {
+            match &self.rects.get(key)?.contents {
+                RectangleContents::Client(_) => return Some(key),
+                RectangleContents::Pane(pane) => key = pane.active_child()?,
+            }
+        }
+    }
+
+    /// The tab next to the one holding `key` in the given direction, like i3 does: left and right
+    /// go through the tabs of tabbed panes, and up and down through the windows of stacked ones.
+    /// Returns `None` if `key` is at the end of the tabs, or if there's a split pane going that
+    /// way in between, since that one is nearer.
+    pub fn tab_beside(&self, mut key: XcrabKey, direction: Direction) -> Option<XcrabKey> {
+        loop {
+            let parent_key = self.rects[key].parent;
+
+            if parent_key == key {
+                return None;
+            }
+
+            let pane = self.rects[parent_key].unwrap_pane();
+            let mode = pane.shown_mode();
+            let directionality = match mode {
+                PaneMode::Split => pane.directionality,
+                PaneMode::Tabbed => Directionality::Horizontal,
+                PaneMode::Stacked => Directionality::Vertical,
+            };
+
+            if directionality == direction.directionality() {
+                let index = pane.position(key);
+                let neighbour_index = if direction.is_forward() {
+                    index.checked_add(1).filter(|&i| i < pane.children.len())
+                } else {
+                    index.checked_sub(1)
+                };
+
+                if let Some(neighbour_index) = neighbour_index {
+                    return (mode != PaneMode::Split).then(|| pane.children[neighbour_index]);
+                }
+            }
+
+            key = parent_key;
+        }
+    }
+
+    /// Whether `ancestor` is `key` or one of its ancestors.
+    pub fn is_ancestor(&self, ancestor: XcrabKey, mut key: XcrabKey) -> bool {
+        loop {
+            if key == ancestor {
+                return true;
+            }
+
+            let parent_key = self.rects[key].parent;
+
+            if parent_key == key {
+                return false;
+            }
+
+            key = parent_key;
+        }
+    }
+
+    /// Returns the clients at or below `key`, in the order they appear from left to right.
+    pub fn leaves(&self, key: XcrabKey) -> Vec<XcrabKey> {
+        match &self.rects[key].contents {
+            RectangleContents::Client(_) => vec![key],
+            RectangleContents::Pane(pane) => pane
+                .children
+                .iter()
+                .flat_map(|&child| self.leaves(child))
+                .collect(),
+        }
+    }
+
+    /// Returns `key` and all of its descendants, parents before their children.
+    pub fn subtree(&self, key: XcrabKey) -> Vec<XcrabKey> {
+        let mut keys = vec![key];
+        let mut i = 0;
+
+        while let Some(&key) = keys.get(i) {
+            if let RectangleContents::Pane(pane) = &self.rects[key].contents {
+                keys.extend_from_slice(&pane.children);
+            }
+
+            i += 1;
+        }
+
+        keys
+    }
+
+    /// Walks up the tree from `key` and returns the key of the root rectangle.
+    pub fn root_of(&self, mut key: XcrabKey) -> XcrabKey {
+        loop {
+            let parent = self.rects[key].parent;
+
+            if parent == key {
+                return key;
+            }
+
+            key = parent;
+        }
+    }
+
+    /// Panics if any of the trees rooted at `roots` is in a state that `normalize` should have
+    /// fixed, if parents and children disagree about each other, or if there are rectangles
+    /// that aren't in any of them.
+    #[cfg(any(debug_assertions, test))]
+    pub fn check(&self, roots: impl IntoIterator<Item = XcrabKey>) {
+        let mut reachable = 0;
+
+        for root in roots {
+            assert_eq!(self.rects[root].parent, root, "root {root:?} has a parent");
+
+            for key in self.subtree(root) {
+                reachable += 1;
+
+                let RectangleContents::Pane(pane) = &self.rects[key].contents else {
+                    continue;
+                };
+
+                assert!(!pane.children.is_empty(), "empty pane {key:?}");
+                assert_eq!(pane.children.len(), pane.weights.len());
+
+                if pane.mode == PaneMode::Split {
+                    assert!(pane.children.len() > 1, "split pane {key:?} has one child");
+                }
+
+                for &child in &pane.children {
+                    assert_eq!(
+                        self.rects[child].parent, key,
+                        "{child:?} has the wrong parent"
+                    );
+
+                    if let RectangleContents::Pane(inner) = &self.rects[child].contents {
+                        assert!(
+                            pane.mode != PaneMode::Split
+                                || inner.mode != PaneMode::Split
+                                || inner.directionality != pane.directionality,
+                            "{child:?} should have been merged into {key:?}"
+                        );
+                    }
+                }
+            }
+        }
+
+        assert_eq!(
+            reachable,
+            self.rects.len(),
+            "rects left outside of any tree"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::x11::layout::{Grid, MasterStack, Spiral};
+    use proptest::{collection::vec, prelude::*};
+
+    /// Enough to make panes run out of room on the smaller areas, so they get squashed.
+    const MAX_CLIENTS: usize = 12;
+
+    /// What the tree is laid out with before resizing, like the window manager does.
+    const SPACING: Spacing = Spacing {
+        gap_size: 4,
+        tab_height: 8,
+        min_size: 20,
+    };
+
+    #[derive(Debug, Clone)]
+    enum Op {
+        /// Adds a client next to the one at the index, in the nearest pane going the right way.
+        Add(usize, Direction),
+        Remove(usize),
+        /// Takes a client out of the tree like floating it does.
+        Float(usize),
+        /// Puts the client that was floated first back where it came from.
+        Unfloat,
+        Move(usize, Direction),
+        Swap(usize, usize),
+        SetMode(usize, PaneMode),
+        Focus(usize),
+        /// Lays the tree out like the window manager would and then resizes the client.
+        Resize(usize, Direction, ResizeAmount),
+        /// Balances the whole tree.
+        Balance,
+    }
+
+    fn direction() -> impl Strategy<Value = Direction> {
+        prop_oneof![
+            Just(Direction::Up),
+            Just(Direction::Down),
+            Just(Direction::Left),
+            Just(Direction::Right),
+        ]
+    }
+
+    fn mode() -> impl Strategy<Value = PaneMode> {
+        prop_oneof![
+            Just(PaneMode::Split),
+            Just(PaneMode::Tabbed),
+            Just(PaneMode::Stacked),
+        ]
+    }
+
+    fn amount() -> impl Strategy<Value = ResizeAmount> {
+        prop_oneof![
+            (-500..500_i32).prop_map(ResizeAmount::Pixels),
+            (-100..100_i32).prop_map(ResizeAmount::Percent),
+        ]
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            4 => (any::<usize>(), direction()).prop_map(|(index, direction)| Op::Add(index, direction)),
+            1 => any::<usize>().prop_map(Op::Remove),
+            1 => any::<usize>().prop_map(Op::Float),
+            1 => Just(Op::Unfloat),
+            2 => (any::<usize>(), direction()).prop_map(|(index, direction)| Op::Move(index, direction)),
+            1 => (any::<usize>(), any::<usize>()).prop_map(|(a, b)| Op::Swap(a, b)),
+            1 => (any::<usize>(), mode()).prop_map(|(index, mode)| Op::SetMode(index, mode)),
+            1 => any::<usize>().prop_map(Op::Focus),
+            2 => (any::<usize>(), direction(), amount())
+                .prop_map(|(index, direction, amount)| Op::Resize(index, direction, amount)),
+            1 => Just(Op::Balance),
+        ]
+    }
+
+    fn area() -> impl Strategy<Value = Dimensions> {
+        Dimensions::strategy(0..100_u16, 1500..3000_u16)
+    }
+
+    fn spacing() -> impl Strategy<Value = Spacing> {
+        (0..8_u16, 0..10_u16, 1..400_u16).prop_map(|(gap_size, tab_height, min_size)| Spacing {
+            gap_size,
+            tab_height,
+            min_size,
+        })
+    }
+
+    /// Areas too small for anything to fit, possibly right at the edge of what `u16` can hold.
+    fn tiny_area() -> impl Strategy<Value = Dimensions> {
+        Dimensions::strategy(
+            prop_oneof![0..100_u16, (u16::MAX - 500)..=(u16::MAX - 400)],
+            0..400_u16,
+        )
+    }
+
+    fn huge_spacing() -> impl Strategy<Value = Spacing> {
+        (0..200_u16, 0..200_u16, 0..200_u16).prop_map(|(gap_size, tab_height, min_size)| Spacing {
+            gap_size,
+            tab_height,
+            min_size,
+        })
+    }
+
+    /// The tree of a single workspace, with numbers standing in for clients.
+    #[derive(Default)]
+    struct Harness {
+        tree: Tree<u32>,
+        root: Option<XcrabKey>,
+        floating: Vec<(u32, Option<TreeSlot>)>,
+        /// Every client that hasn't been removed, tiled or not.
+        clients: Vec<u32>,
+        next: u32,
+    }
+
+    impl Harness {
+        fn run(ops: &[Op], area: Dimensions) -> Self {
+            let mut harness = Self::default();
+
+            for op in ops {
+                harness.apply(op, area);
+                harness.tree.check(harness.root);
+            }
+
+            harness
+        }
+
+        fn leaves(&self) -> Vec<XcrabKey> {
+            self.root
+                .map(|root| self.tree.leaves(root))
+                .unwrap_or_default()
+        }
+
+        /// The tiled client at `index`, wrapping around.
+        fn pick(&self, index: usize) -> Option<XcrabKey> {
+            let leaves = self.leaves();
+
+            (!leaves.is_empty()).then(|| leaves[index % leaves.len()])
+        }
+
+        /// Tiles `client` next to the first client, or as the root if there are none.
+        fn tile(&mut self, client: u32, area: Dimensions) -> XcrabKey {
+            match self.pick(0) {
+                Some(anchor) => {
+                    self.tree
+                        .insert_near(anchor, client, Direction::Right, &mut self.root)
+                }
+                None => *self.root.insert(self.tree.insert_root(client, area)),
+            }
+        }
+
+        fn apply(&mut self, op: &Op, area: Dimensions) {
+            match *op {
+                Op::Add(index, direction) => {
+                    if self.clients.len() >= MAX_CLIENTS {
+                        return;
+                    }
+
+                    self.next += 1;
+                    self.clients.push(self.next);
+
+                    let key = match self.pick(index) {
+                        Some(anchor) => {
+                            self.tree
+                                .insert_near(anchor, self.next, direction, &mut self.root)
+                        }
+                        None => self.tile(self.next, area),
+                    };
+
+                    // new clients get focus, like in the window manager
+                    self.tree.mark_active(key);
+                }
+                Op::Remove(index) => {
+                    if let Some(key) = self.pick(index) {
+                        let (client, _) = self.tree.take(key, &mut self.root);
+                        self.clients.retain(|&c| c != client);
+                    }
+                }
+                Op::Float(index) => {
+                    if let Some(key) = self.pick(index) {
+                        let floating = self.tree.take(key, &mut self.root);
+                        self.floating.push(floating);
+                    }
+                }
+                Op::Unfloat => {
+                    if self.floating.is_empty() {
+                        return;
+                    }
+
+                    let (client, slot) = self.floating.remove(0);
+                    let restored = slot
+                        .and_then(|slot| self.tree.insert_at_slot(client, slot, &mut self.root));
+
+                    if restored.is_none() {
+                        self.tile(client, area);
+                    }
+                }
+                Op::Move(index, direction) => {
+                    if let Some(key) = self.pick(index) {
+                        self.tree.move_rect(key, direction, &mut self.root);
+                    }
+                }
+                Op::Swap(a, b) => {
+                    if let (Some(a), Some(b)) = (self.pick(a), self.pick(b)) {
+                        if a != b {
+                            self.tree.swap(a, b);
+                        }
+                    }
+                }
+                Op::SetMode(index, mode) => {
+                    if let Some(key) = self.pick(index) {
+                        self.tree.set_mode(key, mode, &mut self.root);
+                    }
+                }
+                Op::Focus(index) => {
+                    if let Some(key) = self.pick(index) {
+                        self.tree.mark_active(key);
+                    }
+                }
+                Op::Resize(index, direction, amount) => {
+                    if let (Some(root), Some(key)) = (self.root, self.pick(index)) {
+                        // resizing goes by the sizes from the last layout
+                        self.tree.layout(root, Some(area), SPACING);
+                        self.tree.resize(key, direction, amount, SPACING.gap_size);
+                    }
+                }
+                Op::Balance => {
+                    if let Some(root) = self.root {
+                        self.tree.balance(root);
+                    }
+                }
+            }
+        }
+    }
+
+    fn size(dimensions: Dimensions) -> u64 {
+        u64::from(dimensions.width) * u64::from(dimensions.height)
+    }
+
+    fn overlap(a: Dimensions, b: Dimensions) -> bool {
+        let (a_right, a_bottom) = (
+            u32::from(a.x) + u32::from(a.width),
+            u32::from(a.y) + u32::from(a.height),
+        );
+        let (b_right, b_bottom) = (
+            u32::from(b.x) + u32::from(b.width),
+            u32::from(b.y) + u32::from(b.height),
+        );
+
+        u32::from(a.x) < b_right
+            && u32::from(b.x) < a_right
+            && u32::from(a.y) < b_bottom
+            && u32::from(b.y) < a_bottom
+    }
+
+    fn inside(outer: Dimensions, inner: Dimensions) -> bool {
+        inner.x >= outer.x
+            && inner.y >= outer.y
+            && u32::from(inner.x) + u32::from(inner.width)
+                <= u32::from(outer.x) + u32::from(outer.width)
+            && u32::from(inner.y) + u32::from(inner.height)
+                <= u32::from(outer.y) + u32::from(outer.height)
+    }
+
+    proptest! {
+        #[test]
+        fn tree_stays_normalized(ops in vec(op(), 0..40), area in area()) {
+            // `run` checks the tree after every step
+            Harness::run(&ops, area);
+        }
+
+        #[test]
+        fn clients_are_never_lost(ops in vec(op(), 0..40), area in area()) {
+            let harness = Harness::run(&ops, area);
+
+            let mut held: Vec<u32> = harness
+                .leaves()
+                .into_iter()
+                .map(|key| *harness.tree[key].unwrap_client())
+                .chain(harness.floating.iter().map(|&(client, _)| client))
+                .collect();
+            held.sort_unstable();
+
+            prop_assert_eq!(held, harness.clients);
+        }
+
+        #[test]
+        fn tiles_do_not_overlap(
+            ops in vec(op(), 0..40),
+            area in area(),
+            spacing in spacing(),
+        ) {
+            let mut harness = Harness::run(&ops, area);
+            let Some(root) = harness.root else {
+                return Ok(());
+            };
+
+            let placements = harness.tree.layout(root, Some(area), spacing);
+            prop_assert_eq!(placements.clients.len(), harness.leaves().len());
+
+            for &(_, tile) in &placements.clients {
+                prop_assert!(inside(area, tile), "{tile:?} is outside of {area:?}");
+            }
+
+            let visible: Vec<Dimensions> = placements
+                .clients
+                .iter()
+                .filter(|&&(key, _)| harness.tree.is_visible(key))
+                .chain(
+                    placements
+                        .tab_bars
+                        .iter()
+                        .filter(|&&(key, _)| harness.tree.is_visible(key)),
+                )
+                .map(|&(_, dimensions)| dimensions)
+                .collect();
+
+            for (i, &a) in visible.iter().enumerate() {
+                for &b in &visible[i + 1..] {
+                    prop_assert!(!overlap(a, b), "{a:?} overlaps {b:?}");
+                }
+            }
+        }
+
+        #[test]
+        fn tiles_cover_the_area_minus_gaps(
+            ops in vec(op(), 0..40),
+            area in area(),
+            spacing in spacing(),
+        ) {
+            let mut harness = Harness::run(&ops, area);
+            let Some(root) = harness.root else {
+                return Ok(());
+            };
+
+            let placements = harness.tree.layout(root, Some(area), spacing);
+            let tree = &harness.tree;
+
+            let tiles: u64 = placements
+                .clients
+                .iter()
+                .filter(|&&(key, _)| tree.is_visible(key))
+                .map(|&(_, tile)| size(tile))
+                .sum();
+
+            let tab_bars: u64 = placements
+                .tab_bars
+                .iter()
+                .filter(|&&(key, _)| tree.is_visible(key))
+                .map(|&(_, tab_bar)| size(tab_bar))
+                .sum();
+
+            // every split pane that can be seen has a gap between each of its children
+            let gaps: u64 = tree
+                .subtree(root)
+                .into_iter()
+                .filter(|&key| tree.is_visible(key))
+                .filter_map(|key| match &tree[key].contents {
+                    RectangleContents::Pane(pane) if pane.shown_mode() == PaneMode::Split => {
+                        let across = match pane.directionality {
+                            Directionality::Horizontal => Directionality::Vertical,
+                            Directionality::Vertical => Directionality::Horizontal,
+                        };
+                        let count = pane.children.len() as u64 - 1;
+
+                        Some(count * u64::from(spacing.gap_size) * u64::from(tree[key].cached_dimensions.length(across)))
+                    }
+                    _ => None,
+                })
+                .sum();
+
+            prop_assert_eq!(tiles + tab_bars + gaps, size(area));
+        }
+
+        #[test]
+        fn clients_get_their_minimum_size(
+            ops in vec(op(), 0..40),
+            area in area(),
+            spacing in spacing(),
+        ) {
+            let mut harness = Harness::run(&ops, area);
+            let Some(root) = harness.root else {
+                return Ok(());
+            };
+
+            let tree = &harness.tree;
+            let fits = tree.min_length(root, Directionality::Horizontal, spacing) <= area.width
+                && tree.min_length(root, Directionality::Vertical, spacing) <= area.height;
+
+            let placements = harness.tree.layout(root, Some(area), spacing);
+            let tree = &harness.tree;
+
+            if !fits {
+                return Ok(());
+            }
+
+            // a squashed horizontal pane needs room for its tab bar that `min_length` doesn't
+            // count, so heights are only promised if nothing was squashed
+            let squashed = tree.subtree(root).into_iter().any(|key| {
+                matches!(&tree[key].contents, RectangleContents::Pane(pane) if pane.squashed)
+            });
+
+            for &(key, tile) in &placements.clients {
+                if !tree.is_visible(key) {
+                    continue;
+                }
+
+                prop_assert!(tile.width >= spacing.min_size, "{tile:?} is too narrow");
+                prop_assert!(squashed || tile.height >= spacing.min_size, "{tile:?} is too short");
+            }
+        }
+
+        #[test]
+        fn tiny_areas_do_not_panic(
+            ops in vec(op(), 0..40),
+            area in tiny_area(),
+            spacing in huge_spacing(),
+        ) {
+            let mut harness = Harness::run(&ops, area);
+
+            if let Some(root) = harness.root {
+                let placements = harness.tree.layout(root, Some(area), spacing);
+
+                for &(_, tile) in placements.clients.iter().chain(&placements.tab_bars) {
+                    prop_assert!(inside(area, tile), "{tile:?} is outside of {area:?}");
+                }
+            }
+
+            let layouts: [&dyn Layout; 4] = [
+                &MasterStack { ratio: 0.6 },
+                &Grid,
+                &Spiral { dwindle: false },
+                &Spiral { dwindle: true },
+            ];
+
+            for layout in layouts {
+                for tile in layout.arrange(area, harness.clients.len(), spacing.gap_size) {
+                    prop_assert!(inside(area, tile), "{tile:?} is outside of {area:?}");
+                }
+            }
+        }
+
+        #[test]
+        fn distribute_hands_out_everything(
+            amount in 0..2000_u16,
+            parts in vec((0.01..10.0_f64, 0..300_u16), 1..10),
+        ) {
+            let (weights, minimums): (Vec<f64>, Vec<u16>) = parts.into_iter().unzip();
+            let needed: u32 = minimums.iter().map(|&min| u32::from(min)).sum();
+
+            let Some(sizes) = distribute(amount, &weights, &minimums) else {
+                prop_assert!(needed > u32::from(amount));
+                return Ok(());
+            };
+
+            prop_assert_eq!(sizes.iter().map(|&size| u32::from(size)).sum::<u32>(), u32::from(amount));
+
+            for (size, min) in sizes.iter().zip(&minimums) {
+                prop_assert!(size >= min, "{size} is less than {min}");
+            }
+        }
+
+        #[test]
+        fn automatic_layouts_do_not_overlap(
+            count in 1..=MAX_CLIENTS,
+            area in area(),
+            spacing in spacing(),
+        ) {
+            let layouts: [&dyn Layout; 4] = [
+                &MasterStack { ratio: 0.6 },
+                &Grid,
+                &Spiral { dwindle: false },
+                &Spiral { dwindle: true },
+            ];
+
+            for layout in layouts {
+                let tiles = layout.arrange(area, count, spacing.gap_size);
+                prop_assert_eq!(tiles.len(), count);
+
+                for (i, &a) in tiles.iter().enumerate() {
+                    prop_assert!(inside(area, a), "{a:?} is outside of {area:?}");
+
+                    for &b in &tiles[i + 1..] {
+                        prop_assert!(!overlap(a, b), "{a:?} overlaps {b:?}");
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn distances_go_one_way(
+            a in Dimensions::strategy(0..2000_u16, 0..500_u16),
+            b in Dimensions::strategy(0..2000_u16, 0..500_u16),
+            direction in direction(),
+        ) {
+            let opposite = match direction {
+                Direction::Up => Direction::Down,
+                Direction::Down => Direction::Up,
+                Direction::Left => Direction::Right,
+                Direction::Right => Direction::Left,
+            };
+
+            // going back the other way is just as far
+            prop_assert_eq!(a.distance_to(b, direction), b.distance_to(a, opposite));
+
+            if let Some((primary, secondary)) = a.distance_to(b, direction) {
+                let edges = |d: Dimensions| {
+                    let (x, y) = (i64::from(d.x), i64::from(d.y));
+                    (x, y, x + i64::from(d.width), y + i64::from(d.height))
+                };
+                let (left, top, right, bottom) = edges(a);
+                let (b_left, b_top, b_right, b_bottom) = edges(b);
+
+                // how far `b` is past our edge, and whether it overlaps us the other way
+                let (gap, lined_up) = match direction {
+                    Direction::Left => (left - b_right, b_bottom >= top && bottom >= b_top),
+                    Direction::Right => (b_left - right, b_bottom >= top && bottom >= b_top),
+                    Direction::Up => (top - b_bottom, b_right >= left && right >= b_left),
+                    Direction::Down => (b_top - bottom, b_right >= left && right >= b_left),
+                };
+
+                prop_assert_eq!(gap, i64::from(primary));
+                prop_assert_eq!(lined_up, secondary == 0);
+            }
+        }
+
+        #[test]
+        fn the_nearest_lined_up_client_wins(
+            from in Dimensions::strategy(500..1500_u16, 1..500_u16),
+            candidates in vec(Dimensions::strategy(0..2000_u16, 1..500_u16), 0..8),
+            direction in direction(),
+        ) {
+            let candidates: Vec<(usize, Dimensions)> = candidates.into_iter().enumerate().collect();
+            let nearest = from.nearest(candidates.iter().copied(), direction);
+
+            let distances: Vec<(usize, (u32, u32))> = candidates
+                .iter()
+                .filter_map(|&(id, other)| Some((id, from.distance_to(other, direction)?)))
+                .collect();
+
+            prop_assert_eq!(nearest.is_some(), !distances.is_empty());
+
+            if let Some(nearest) = nearest {
+                let (_, (primary, secondary)) = distances[distances.iter().position(|&(id, _)| id == nearest).unwrap()];
+
+                for &(id, (other_primary, other_secondary)) in &distances {
+                    // anything that lines up beats anything that doesn't
+                    if secondary > 0 {
+                        prop_assert!(other_secondary > 0);
+                    } else if other_secondary == 0 {
+                        prop_assert!((primary, nearest) <= (other_primary, id));
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn resizing_grows_the_requested_side(
+            ops in vec(op(), 0..40),
+            area in area(),
+            index in any::<usize>(),
+            direction in direction(),
+            pixels in 1..300_i32,
+        ) {
+            let mut harness = Harness::run(&ops, area);
+            let (Some(root), Some(key)) = (harness.root, harness.pick(index)) else {
+                return Ok(());
+            };
+            let squashed = |tree: &Tree<u32>| -> Vec<bool> {
+                tree.subtree(root)
+                    .into_iter()
+                    .map(|key| matches!(&tree[key].contents, RectangleContents::Pane(pane) if pane.squashed))
+                    .collect()
+            };
+
+            harness.tree.layout(root, Some(area), SPACING);
+            let squashed_before = squashed(&harness.tree);
+
+            let Some(pane_key) = harness.tree.resize(key, direction, ResizeAmount::Pixels(pixels), SPACING.gap_size) else {
+                return Ok(());
+            };
+
+            // the child of the resized pane that holds the client
+            let mut grown = key;
+            while harness.tree[grown].parent != pane_key {
+                grown = harness.tree[grown].parent;
+            }
+
+            let before = harness.tree[grown].cached_dimensions;
+            harness.tree.layout(root, Some(area), SPACING);
+            let after = harness.tree[grown].cached_dimensions;
+
+            // panes that get squashed into tabs by the resize are laid out differently altogether
+            if squashed(&harness.tree) != squashed_before {
+                return Ok(());
+            }
+
+            // where a rectangle starts and ends, going in the direction of the resize
+            let edges = |dimensions: Dimensions| {
+                let (start, length) = match direction.directionality() {
+                    Directionality::Horizontal => (dimensions.x, dimensions.width),
+                    Directionality::Vertical => (dimensions.y, dimensions.height),
+                };
+                let (start, end) = (i32::from(start), i32::from(start) + i32::from(length));
+
+                if direction.is_forward() { (start, end) } else { (-end, -start) }
+            };
+
+            // the siblings on the other side keep their weights, but each of them can still gain
+            // or lose a pixel of rounding
+            let pane = harness.tree[pane_key].unwrap_pane();
+            let index = pane.position(grown);
+            let behind = if direction.is_forward() { index } else { pane.children.len() - 1 - index };
+            let behind = i32::try_from(behind).unwrap();
+
+            // the edge on the requested side moves out, and the other one stays where it was
+            let (near_before, far_before) = edges(before);
+            let (near_after, far_after) = edges(after);
+
+            prop_assert!(far_after >= far_before, "{:?} shrank to {:?}", before, after);
+            prop_assert!((near_after - near_before).abs() <= behind, "{:?} moved to {:?}", before, after);
+
+        }
+
+        #[test]
+        fn focus_steps_through_tabs(
+            ops in vec(op(), 0..40),
+            area in area(),
+            index in any::<usize>(),
+            direction in direction(),
+        ) {
+            let mut harness = Harness::run(&ops, area);
+            let Some(key) = harness.pick(index) else {
+                return Ok(());
+            };
+
+            // focus is always on something that can be seen
+            harness.tree.mark_active(key);
+            let tree = &mut harness.tree;
+
+            let Some(tab) = tree.tab_beside(key, direction) else {
+                return Ok(());
+            };
+
+            // it's the tab right next to the one holding `key`
+            let pane_key = tree[tab].parent;
+            let pane = tree[pane_key].unwrap_pane();
+            prop_assert_ne!(pane.shown_mode(), PaneMode::Split);
+
+            let mut current = key;
+            while tree[current].parent != pane_key {
+                current = tree[current].parent;
+            }
+
+            let (from, to) = (pane.position(current), pane.position(tab));
+            prop_assert_eq!(to, if direction.is_forward() { from + 1 } else { from - 1 });
+
+            // and focusing it swaps which one is shown
+            let leaf = tree.active_leaf(tab).unwrap();
+            tree.mark_active(leaf);
+
+            prop_assert!(tree.is_visible(leaf));
+            prop_assert!(!tree.is_visible(key));
+        }
+
+        #[test]
+        fn swapping_keeps_the_shown_tabs(
+            ops in vec(op(), 0..40),
+            area in area(),
+            a in any::<usize>(),
+            b in any::<usize>(),
+        ) {
+            let mut harness = Harness::run(&ops, area);
+            let (Some(a), Some(b)) = (harness.pick(a), harness.pick(b)) else {
+                return Ok(());
+            };
+
+            if a == b {
+                return Ok(());
+            }
+
+            let shown = |tree: &Tree<u32>, keys: &[XcrabKey]| -> Vec<bool> {
+                keys.iter().map(|&key| tree.is_visible(key)).collect()
+            };
+
+            let others: Vec<XcrabKey> = harness
+                .leaves()
+                .into_iter()
+                .filter(|&key| key != a && key != b)
+                .collect();
+            let before = shown(&harness.tree, &others);
+            let mut pair_before = shown(&harness.tree, &[a, b]);
+            pair_before.sort_unstable();
+
+            harness.tree.swap(a, b);
+            harness.tree.check(harness.root);
+
+            // nothing else gets shown or hidden, and a shown tab stays shown
+            prop_assert_eq!(shown(&harness.tree, &others), before);
+
+            let mut pair = shown(&harness.tree, &[a, b]);
+            pair.sort_unstable();
+            prop_assert_eq!(pair, pair_before);
+        }
+    }
+}